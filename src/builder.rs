@@ -10,6 +10,8 @@ pub struct Builder {
     config: Config,
 
     link: config::Link,
+
+    dns: config::Dns,
 }
 
 impl Default for Builder {
@@ -26,7 +28,9 @@ impl Builder {
             link: config::Link {
                 latency: Some(config::Latency::default()),
                 message_loss: Some(config::MessageLoss::default()),
+                ..config::Link::default()
             },
+            dns: config::Dns::default(),
         }
     }
 
@@ -48,12 +52,54 @@ impl Builder {
         self
     }
 
+    /// Whether `tokio::time` in hosts and clients follows simulated time.
+    ///
+    /// Enabled by default: each runtime's clock is paused and advanced by the
+    /// tick duration as the simulation steps, so sleeps and timeouts wake at
+    /// the appropriate simulated time. When disabled, each tick waits for the
+    /// tick duration in wall-clock time instead.
+    pub fn use_simulated_time(&mut self, value: bool) -> &mut Self {
+        self.config.simulated_time = value;
+        self
+    }
+
+    /// Whether to record the messages delivered and dropped by the network,
+    /// see [`Sim::message_log`], and the events on the [`Sim::timeline`].
+    ///
+    /// Disabled by default, as the records are kept until cleared with
+    /// [`Sim::clear_message_log`].
+    pub fn record_messages(&mut self, value: bool) -> &mut Self {
+        self.config.record_messages = value;
+        self
+    }
+
     /// Set the random number generator used to fuzz
     pub fn rng(&mut self, rng: impl RngCore + 'static) -> &mut Self {
         self.rng = Some(Box::new(rng));
         self
     }
 
+    /// How long [`resolve_async`] takes in simulated time when the name is
+    /// not cached by the resolving host. Defaults to zero.
+    pub fn dns_latency(&mut self, value: Duration) -> &mut Self {
+        self.dns.latency = value;
+        self
+    }
+
+    /// How long hosts cache the results of [`resolve_async`], including
+    /// names that failed to resolve. Defaults to zero, which disables caching.
+    pub fn dns_ttl(&mut self, value: Duration) -> &mut Self {
+        self.dns.ttl = value;
+        self
+    }
+
+    /// Assign host addresses as `dns` does, e.g. with a custom allocator from
+    /// [`Dns::with_custom_allocator`].
+    pub fn dns(&mut self, dns: Dns) -> &mut Self {
+        self.dns.allocator = dns.config.allocator;
+        self
+    }
+
     pub fn min_message_latency(&mut self, value: Duration) -> &mut Self {
         self.link
             .latency
@@ -87,7 +133,7 @@ impl Builder {
     }
 
     pub fn build_with_rng<'a>(&self, rng: Box<dyn RngCore>) -> Sim<'a> {
-        let world = World::new(self.link.clone(), rng);
+        let world = World::new(self.link.clone(), self.dns.clone(), rng);
         Sim::new(self.config.clone(), world)
     }
 }
@@ -0,0 +1,452 @@
+//! Randomized fault injection.
+//!
+//! A [`ChaosMonkey`] drives a [`Sim`] and, on each step, may inject a fault
+//! between a random pair of hosts. This is useful for running a battery of
+//! resilience tests without hand writing each failure scenario.
+
+use crate::{config, FlakyLinkConfig, Result, Sim, TRACING_TARGET};
+
+use rand::rngs::SmallRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::net::IpAddr;
+use std::time::Duration;
+
+/// The kinds of faults a [`ChaosMonkey`] may inject.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// Messages between two hosts are randomly dropped for a while.
+    LossSpike,
+
+    /// Two hosts are partitioned for a while.
+    Partition,
+
+    /// A host's software is crashed and immediately restarted.
+    CrashRestart,
+
+    /// Messages between two hosts are delayed for a while.
+    LatencySpike,
+}
+
+impl Fault {
+    /// All supported fault kinds.
+    pub const ALL: [Fault; 4] = [
+        Fault::LossSpike,
+        Fault::Partition,
+        Fault::CrashRestart,
+        Fault::LatencySpike,
+    ];
+}
+
+/// Faults applied to a whole simulation for the scope of
+/// [`Sim::with_chaos`].
+#[derive(Debug, Clone)]
+pub struct ChaosConfig {
+    /// Probability of a link failing with each message sent, see
+    /// [`Sim::set_fail_rate`]. Links with their own fail rate keep it.
+    pub fail_rate: f64,
+
+    /// Probability of a failed link being repaired with each message sent.
+    pub repair_rate: f64,
+
+    /// Max message latency of links without their own latency.
+    pub max_message_latency: Option<Duration>,
+
+    /// Faults applied to every link, see [`Sim::add_flaky_link`]. This
+    /// replaces the latency of each link.
+    pub links: Option<FlakyLinkConfig>,
+}
+
+impl Default for ChaosConfig {
+    fn default() -> Self {
+        Self {
+            fail_rate: 0.0,
+            repair_rate: 1.0,
+            max_message_latency: None,
+            links: None,
+        }
+    }
+}
+
+/// Randomly applies faults to a simulation as it steps.
+///
+/// The monkey uses its own seeded rng, so the faults injected are reproducible
+/// given the same seed and the same simulation.
+///
+/// ```
+/// use turmoil::chaos::{ChaosMonkey, Fault};
+///
+/// let mut sim = turmoil::Builder::new().build();
+///
+/// sim.host("a", || async { std::future::pending().await });
+/// sim.host("b", || async { std::future::pending().await });
+///
+/// sim.client("test", async {
+///     tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+///
+///     Ok(())
+/// });
+///
+/// let mut monkey = ChaosMonkey::new(42, 0.01);
+/// monkey.faults(&[Fault::Partition, Fault::CrashRestart]);
+///
+/// monkey.run(&mut sim).unwrap();
+/// ```
+pub struct ChaosMonkey {
+    rng: SmallRng,
+
+    /// Probability of injecting a fault each step.
+    fault_rate: f64,
+
+    /// Which faults may be injected.
+    faults: Vec<Fault>,
+
+    /// How long link faults last before being healed.
+    fault_duration: Duration,
+
+    /// Fail rate applied to a link during a [`Fault::LossSpike`].
+    loss_spike_rate: f64,
+
+    /// Latency applied to a link during a [`Fault::LatencySpike`].
+    latency_spike: Duration,
+
+    /// Link faults that have not yet been healed.
+    active: Vec<Active>,
+}
+
+/// A link fault that is in effect until `until`.
+struct Active {
+    pair: (IpAddr, IpAddr),
+    until: Duration,
+    heal: Heal,
+}
+
+/// How to undo a link fault.
+enum Heal {
+    Repair,
+    Restore(Box<config::Link>),
+}
+
+impl ChaosMonkey {
+    /// Create a monkey that injects one of all [`Fault`] kinds with
+    /// probability `fault_rate` on each step.
+    pub fn new(seed: u64, fault_rate: f64) -> Self {
+        Self {
+            rng: SmallRng::seed_from_u64(seed),
+            fault_rate,
+            faults: Fault::ALL.to_vec(),
+            fault_duration: Duration::from_secs(1),
+            loss_spike_rate: 0.5,
+            latency_spike: Duration::from_millis(500),
+            active: vec![],
+        }
+    }
+
+    /// Restrict the faults that may be injected.
+    pub fn faults(&mut self, faults: &[Fault]) -> &mut Self {
+        self.faults = faults.to_vec();
+        self
+    }
+
+    /// How long link faults last in simulated time before being healed.
+    pub fn fault_duration(&mut self, value: Duration) -> &mut Self {
+        self.fault_duration = value;
+        self
+    }
+
+    /// The link fail rate used for [`Fault::LossSpike`].
+    pub fn loss_spike_rate(&mut self, value: f64) -> &mut Self {
+        self.loss_spike_rate = value;
+        self
+    }
+
+    /// The link latency used for [`Fault::LatencySpike`].
+    pub fn latency_spike(&mut self, value: Duration) -> &mut Self {
+        self.latency_spike = value;
+        self
+    }
+
+    /// Run the simulation to completion, injecting faults along the way.
+    pub fn run(&mut self, sim: &mut Sim) -> Result {
+        loop {
+            let is_finished = self.step(sim)?;
+
+            if is_finished {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Heal expired faults, maybe inject a new one and then step the
+    /// simulation.
+    ///
+    /// Returns whether or not all clients have completed.
+    pub fn step(&mut self, sim: &mut Sim) -> Result<bool> {
+        self.heal_expired(sim);
+
+        if !self.faults.is_empty() && self.rng.gen_bool(self.fault_rate) {
+            let fault = *self.faults.choose(&mut self.rng).unwrap();
+            self.inject(sim, fault);
+        }
+
+        sim.step()
+    }
+
+    /// Immediately heal all active link faults.
+    pub fn heal_all(&mut self, sim: &mut Sim) {
+        for active in self.active.drain(..) {
+            heal(sim, active);
+        }
+    }
+
+    fn heal_expired(&mut self, sim: &mut Sim) {
+        let now = sim.elapsed();
+        let (expired, active) = self.active.drain(..).partition(|a| a.until <= now);
+        self.active = active;
+
+        for active in expired {
+            heal(sim, active);
+        }
+    }
+
+    fn inject(&mut self, sim: &mut Sim, fault: Fault) {
+        if let Fault::CrashRestart = fault {
+            let hosts = sim
+                .hosts()
+                .into_iter()
+                .filter_map(|(addr, is_host)| is_host.then_some(addr))
+                .collect::<Vec<_>>();
+
+            if let Some(&addr) = hosts.choose(&mut self.rng) {
+                tracing::info!(target: TRACING_TARGET, ?addr, ?fault, "Chaos");
+                sim.bounce(addr);
+            }
+
+            return;
+        }
+
+        let Some(pair) = self.choose_pair(sim) else {
+            return;
+        };
+
+        tracing::info!(target: TRACING_TARGET, a = ?pair.0, b = ?pair.1, ?fault, "Chaos");
+
+        let mut world = sim.world.borrow_mut();
+        let heal = match fault {
+            Fault::Partition => {
+                world.partition(pair.0, pair.1);
+                Heal::Repair
+            }
+            Fault::LossSpike => {
+                let saved = world.topology.link_config(pair.0, pair.1);
+                world
+                    .topology
+                    .set_link_fail_rate(pair.0, pair.1, self.loss_spike_rate);
+                Heal::Restore(Box::new(saved))
+            }
+            Fault::LatencySpike => {
+                let saved = world.topology.link_config(pair.0, pair.1);
+                world
+                    .topology
+                    .set_link_message_latency(pair.0, pair.1, self.latency_spike);
+                Heal::Restore(Box::new(saved))
+            }
+            Fault::CrashRestart => unreachable!(),
+        };
+
+        self.active.push(Active {
+            pair,
+            until: sim.elapsed() + self.fault_duration,
+            heal,
+        });
+    }
+
+    /// Choose two distinct hosts whose link is not already faulted.
+    fn choose_pair(&mut self, sim: &Sim) -> Option<(IpAddr, IpAddr)> {
+        let addrs = sim
+            .hosts()
+            .into_iter()
+            .map(|(addr, _)| addr)
+            .collect::<Vec<_>>();
+
+        let mut pairs = vec![];
+        for (i, a) in addrs.iter().enumerate() {
+            for b in &addrs[i + 1..] {
+                let is_active = self
+                    .active
+                    .iter()
+                    .any(|active| active.pair == (*a, *b) || active.pair == (*b, *a));
+
+                if !is_active {
+                    pairs.push((*a, *b));
+                }
+            }
+        }
+
+        pairs.choose(&mut self.rng).copied()
+    }
+}
+
+fn heal(sim: &mut Sim, active: Active) {
+    let (a, b) = active.pair;
+    let mut world = sim.world.borrow_mut();
+
+    tracing::info!(target: TRACING_TARGET, ?a, ?b, "Heal");
+
+    match active.heal {
+        Heal::Repair => world.repair(a, b),
+        Heal::Restore(config) => world.topology.set_link_config(a, b, *config),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        future,
+        net::{IpAddr, Ipv4Addr},
+        panic::AssertUnwindSafe,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use crate::{
+        chaos::{ChaosConfig, ChaosMonkey, Fault},
+        net::UdpSocket,
+        Builder, FlakyLinkConfig, Result,
+    };
+
+    #[test]
+    fn crash_restart() -> Result {
+        let mut sim = Builder::new().build();
+
+        let starts = Arc::new(AtomicU64::new(0));
+        let count = starts.clone();
+
+        sim.host("host", move || {
+            let count = count.clone();
+
+            async move {
+                count.fetch_add(1, Ordering::SeqCst);
+                future::pending().await
+            }
+        });
+
+        sim.client("client", async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+
+            Ok(())
+        });
+
+        let mut monkey = ChaosMonkey::new(0, 1.0);
+        monkey.faults(&[Fault::CrashRestart]);
+        monkey.run(&mut sim)?;
+
+        // the client is never restarted, so every fault bounces the host
+        assert!(starts.load(Ordering::SeqCst) > 100);
+
+        Ok(())
+    }
+
+    #[test]
+    fn with_chaos() -> Result {
+        let mut sim = Builder::new().build();
+
+        sim.client("server", async {
+            let sock = UdpSocket::bind((IpAddr::V4(Ipv4Addr::UNSPECIFIED), 1234)).await?;
+
+            let mut buf = [0];
+            sock.recv_from(&mut buf).await?;
+            assert_eq!([2], buf);
+
+            Ok(())
+        });
+
+        sim.client("client", async {
+            let sock = UdpSocket::bind((IpAddr::V4(Ipv4Addr::UNSPECIFIED), 1234)).await?;
+
+            sock.send_to(&[1], "server:1234").await?;
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            sock.send_to(&[2], "server:1234").await?;
+
+            Ok(())
+        });
+
+        let chaos = ChaosConfig {
+            links: Some(FlakyLinkConfig {
+                loss: 1.0,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let is_finished = sim.with_chaos(chaos, |sim| sim.run_for(Duration::from_millis(500)))?;
+        assert!(!is_finished);
+
+        sim.run()
+    }
+
+    #[test]
+    fn with_chaos_panic() -> Result {
+        let mut sim = Builder::new().build();
+
+        sim.client("server", async {
+            let sock = UdpSocket::bind((IpAddr::V4(Ipv4Addr::UNSPECIFIED), 1234)).await?;
+            sock.recv_from(&mut [0]).await?;
+
+            Ok(())
+        });
+
+        sim.client("client", async {
+            let sock = UdpSocket::bind((IpAddr::V4(Ipv4Addr::UNSPECIFIED), 1234)).await?;
+            sock.send_to(&[1], "server:1234").await?;
+
+            Ok(())
+        });
+
+        let chaos = ChaosConfig {
+            links: Some(FlakyLinkConfig {
+                loss: 1.0,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let res = std::panic::catch_unwind(AssertUnwindSafe(|| {
+            sim.with_chaos(chaos, |_| panic!("boom"));
+        }));
+        assert!(res.is_err());
+
+        // the datagram is only delivered if the loss was restored
+        sim.run()
+    }
+
+    #[test]
+    fn link_faults_heal() -> Result {
+        let mut sim = Builder::new().build();
+
+        sim.host("a", || async { future::pending().await });
+        sim.host("b", || async { future::pending().await });
+
+        let mut monkey = ChaosMonkey::new(0, 1.0);
+        monkey
+            .faults(&[Fault::Partition, Fault::LatencySpike])
+            .fault_duration(Duration::from_millis(10));
+
+        monkey.step(&mut sim)?;
+        assert_eq!(1, monkey.active.len());
+
+        // there is only one link, so no other fault is injected until it heals
+        for _ in 0..9 {
+            monkey.step(&mut sim)?;
+            assert_eq!(1, monkey.active.len());
+        }
+
+        monkey.heal_all(&mut sim);
+        assert!(monkey.active.is_empty());
+
+        Ok(())
+    }
+}
@@ -1,4 +1,7 @@
+use indexmap::IndexMap;
 use rand_distr::Exp;
+use std::net::IpAddr;
+use std::rc::Rc;
 use std::time::{Duration, SystemTime};
 
 #[derive(Clone)]
@@ -11,6 +14,27 @@ pub(crate) struct Config {
 
     /// When the simulation starts
     pub(crate) epoch: SystemTime,
+
+    /// Whether host runtimes run on a paused clock driven by the simulation
+    pub(crate) simulated_time: bool,
+
+    /// Whether delivered and dropped messages, and timeline events, are
+    /// recorded
+    pub(crate) record_messages: bool,
+}
+
+/// Configures simulated DNS resolution, see [`crate::resolve_async`].
+#[derive(Clone, Default)]
+pub(crate) struct Dns {
+    /// How long a resolution that misses the cache takes
+    pub(crate) latency: Duration,
+
+    /// How long a resolution, successful or not, is cached by a host
+    pub(crate) ttl: Duration,
+
+    /// Assigns the address of each new host by index, replacing the default
+    /// 192.168.0.0/16 scheme
+    pub(crate) allocator: Option<Rc<dyn Fn(usize) -> IpAddr>>,
 }
 
 /// Configures link behavior.
@@ -21,6 +45,58 @@ pub(crate) struct Link {
 
     /// How often sending a message works vs. the message getting dropped
     pub(crate) message_loss: Option<MessageLoss>,
+
+    /// Fixed latency of messages toward each end of the link, overriding
+    /// `latency`. Only set for asymmetric links.
+    pub(crate) directional_latency: IndexMap<IpAddr, Duration>,
+
+    /// Probability of a UDP datagram being delivered twice
+    pub(crate) duplication_rate: f64,
+
+    /// Probability of a message overtaking the message sent before it
+    pub(crate) reorder_rate: f64,
+
+    /// Throughput in bytes per second, in each direction
+    pub(crate) bandwidth: Option<u64>,
+
+    /// Corruption of messages toward each end of the link
+    pub(crate) corruption: IndexMap<IpAddr, Corruption>,
+}
+
+/// Flips bits in a random selection of payload bytes, see
+/// [`crate::Sim::enable_packet_corruption`].
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Corruption {
+    /// Probability of corrupting each message
+    pub(crate) probability: f64,
+
+    /// Max number of bytes corrupted in a message
+    pub(crate) max_bytes: usize,
+}
+
+/// Faults applied to a link by [`crate::Sim::add_flaky_link`].
+#[derive(Debug, Clone, Default)]
+pub struct FlakyLinkConfig {
+    /// Probability of dropping each message.
+    pub loss: f64,
+
+    /// Probability of delivering a UDP datagram twice. TCP segments are never
+    /// duplicated.
+    pub duplication: f64,
+
+    /// Probability of a message overtaking the previous message still in
+    /// flight in the same direction.
+    pub reorder: f64,
+
+    /// Minimum message latency.
+    pub latency_base: Duration,
+
+    /// Maximum latency added on top of `latency_base`.
+    pub latency_jitter: Duration,
+
+    /// Throughput in bytes per second, in each direction. Messages wait for
+    /// those sent before them to be transmitted. Zero is unlimited.
+    pub bandwidth: u64,
 }
 
 /// Configure latency behavior between two hosts.
@@ -52,6 +128,8 @@ impl Default for Config {
             duration: Duration::from_secs(10),
             tick: Duration::from_millis(1),
             epoch: SystemTime::now(),
+            simulated_time: true,
+            record_messages: false,
         }
     }
 }
@@ -1,14 +1,53 @@
-use indexmap::IndexMap;
+use indexmap::{IndexMap, IndexSet};
 #[cfg(feature = "regex")]
 use regex::Regex;
-use std::net::{IpAddr, SocketAddr, Ipv4Addr, Ipv6Addr};
+use std::collections::VecDeque;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::rc::Rc;
+use std::time::Duration;
 
-/// Each new host has an IP in the subnet 192.168.0.0/24.
+use crate::config;
+
+/// Each new host has an IP in the subnet 192.168.0.0/16, unless a custom
+/// allocator is configured with [`Dns::with_custom_allocator`].
 pub struct Dns {
     next: u16,
     names: IndexMap<String, IpAddr>,
+    /// Addresses of hosts registered by ip address, and of host interfaces.
+    reserved: IndexSet<IpAddr>,
+    /// Addresses released by removed hosts, by family, reused before
+    /// allocating new ones.
+    freed_v4: VecDeque<Ipv4Addr>,
+    freed_v6: VecDeque<Ipv6Addr>,
+    /// The family of the last address returned by a custom allocator.
+    allocated: Option<Family>,
+    /// Named groups of hosts, see [`Group`].
+    groups: IndexMap<String, Vec<String>>,
+    /// Latency and ttl applied by [`crate::resolve_async`].
+    pub(crate) config: config::Dns,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Family {
+    V4,
+    V6,
 }
 
+/// A host's cache of [`crate::resolve_async`] results, keyed by name.
+///
+/// Failed resolutions are cached as `None`.
+#[derive(Default)]
+pub(crate) struct Cache {
+    entries: IndexMap<String, (Option<IpAddr>, Duration)>,
+}
+
+/// A named group of hosts, registered with [`crate::Sim::add_host_group`].
+///
+/// Resolves to the addresses of all members wherever [`ToIpAddrs`] is
+/// accepted. Members removed with [`crate::Sim::remove_host`] are skipped.
+#[derive(Debug, Clone, Copy)]
+pub struct Group<'a>(pub &'a str);
+
 /// Converts or resolves to an [`IpAddr`].
 pub trait ToIpAddr: sealed::Sealed {
     #[doc(hidden)]
@@ -25,6 +64,12 @@ pub trait ToIpAddrs: sealed::Sealed {
 pub trait ToSocketAddrs: sealed::Sealed {
     #[doc(hidden)]
     fn to_socket_addr(&self, dns: &Dns) -> SocketAddr;
+
+    /// Whether the address is given by hostname rather than ip.
+    #[doc(hidden)]
+    fn is_hostname(&self) -> bool {
+        false
+    }
 }
 
 impl Dns {
@@ -32,9 +77,34 @@ impl Dns {
         Dns {
             next: 1,
             names: IndexMap::new(),
+            reserved: IndexSet::new(),
+            freed_v4: VecDeque::new(),
+            freed_v6: VecDeque::new(),
+            allocated: None,
+            groups: IndexMap::new(),
+            config: config::Dns::default(),
         }
     }
 
+    /// Assign host addresses with `alloc`, rather than sequentially from
+    /// 192.168.0.1. Pass the result to [`crate::Builder::dns`].
+    ///
+    /// `alloc` is called with the index of each new host, starting from 0. It
+    /// must return addresses of a single family that are not yet in use,
+    /// whether by another host or a host interface. Addresses freed by removed
+    /// hosts are reused before new ones are allocated.
+    pub fn with_custom_allocator(alloc: impl Fn(usize) -> IpAddr + 'static) -> Dns {
+        Dns::new().config(config::Dns {
+            allocator: Some(Rc::new(alloc)),
+            ..Default::default()
+        })
+    }
+
+    pub(crate) fn config(mut self, config: config::Dns) -> Dns {
+        self.config = config;
+        self
+    }
+
     pub(crate) fn lookup(&mut self, addr: impl ToIpAddr) -> IpAddr {
         addr.to_ip_addr(self)
     }
@@ -43,12 +113,142 @@ impl Dns {
         addrs.to_ip_addrs(self)
     }
 
+    /// Resolve a registered name or ip address, without registering unknown
+    /// names like [`Dns::lookup`] does.
+    pub(crate) fn resolve(&self, name: &str) -> Option<IpAddr> {
+        name.parse().ok().or_else(|| self.names.get(name).copied())
+    }
+
     pub(crate) fn reverse(&self, addr: IpAddr) -> &str {
+        self.try_reverse(addr)
+            .expect("no hostname found for ip address")
+    }
+
+    /// Hosts registered by [`IpAddr`] don't have a hostname.
+    pub(crate) fn try_reverse(&self, addr: IpAddr) -> Option<&str> {
         self.names
             .iter()
             .find(|(_, a)| **a == addr)
-            .map(|(name, _)| name)
-            .expect("no hostname found for ip address")
+            .map(|(name, _)| &name[..])
+    }
+
+    /// All registered hostnames and their addresses, in registration order.
+    pub(crate) fn entries(&self) -> impl Iterator<Item = (&str, IpAddr)> {
+        self.names.iter().map(|(name, addr)| (&name[..], *addr))
+    }
+
+    /// Whether `addr` is registered, by name, as a host or as an interface.
+    fn is_in_use(&self, addr: IpAddr) -> bool {
+        self.try_reverse(addr).is_some() || self.reserved.contains(&addr)
+    }
+
+    /// Mark `addr` as in use by a host or interface that may not have a name,
+    /// so that it is not allocated to a new host.
+    pub(crate) fn reserve(&mut self, addr: IpAddr) {
+        self.reserved.insert(addr);
+    }
+
+    /// Register a named group of hosts. Panics if any member is not a known
+    /// host.
+    pub(crate) fn add_group(&mut self, name: &str, members: &[&str]) {
+        let members = members
+            .iter()
+            .map(|m| {
+                assert!(self.names.contains_key(*m), "no host found for name: {m}");
+                m.to_string()
+            })
+            .collect();
+
+        self.groups.insert(name.to_string(), members);
+    }
+
+    /// Assign an address to a new host, reusing a freed address of the family
+    /// new hosts are allocated from.
+    fn assign(&mut self) -> IpAddr {
+        let family = match self.config.allocator {
+            Some(_) => self.allocated,
+            None => Some(Family::V4),
+        };
+
+        let freed = match family {
+            Some(Family::V4) => self.freed_v4.pop_front().map(IpAddr::from),
+            Some(Family::V6) => self.freed_v6.pop_front().map(IpAddr::from),
+            None => None,
+        };
+
+        freed.unwrap_or_else(|| self.allocate())
+    }
+
+    /// Allocate a new address.
+    fn allocate(&mut self) -> IpAddr {
+        let host = self.next;
+        self.next += 1;
+
+        if let Some(alloc) = &self.config.allocator {
+            let addr = alloc(host as usize - 1);
+            assert!(
+                !self.is_in_use(addr),
+                "allocated address {addr} is already in use"
+            );
+
+            let family = match addr {
+                IpAddr::V4(_) => Family::V4,
+                IpAddr::V6(_) => Family::V6,
+            };
+            assert!(
+                self.allocated.is_none_or(|allocated| allocated == family),
+                "allocated address {addr} is not of the same family as previously allocated addresses"
+            );
+            self.allocated = Some(family);
+
+            return addr;
+        }
+
+        let a = (host >> 8) as u8;
+        let b = (host & 0xFF) as u8;
+
+        Ipv4Addr::new(192, 168, a, b).into()
+    }
+
+    /// Register `name` at `addr`, which must not already be in use.
+    pub(crate) fn insert(&mut self, name: &str, addr: IpAddr) {
+        assert!(
+            self.try_reverse(addr).is_none(),
+            "{addr} is already registered"
+        );
+
+        match addr {
+            IpAddr::V4(addr) => self.freed_v4.retain(|freed| *freed != addr),
+            IpAddr::V6(addr) => self.freed_v6.retain(|freed| *freed != addr),
+        }
+        self.names.insert(name.to_string(), addr);
+    }
+
+    /// Remove the hostname registered for `addr`, making the address available
+    /// for assignment to a new host. Releases `addr` if it was reserved.
+    pub(crate) fn remove(&mut self, addr: IpAddr) {
+        self.reserved.shift_remove(&addr);
+
+        if let Some(name) = self.try_reverse(addr).map(str::to_string) {
+            self.names.shift_remove(&name);
+            match addr {
+                IpAddr::V4(addr) => self.freed_v4.push_back(addr),
+                IpAddr::V6(addr) => self.freed_v6.push_back(addr),
+            }
+        }
+    }
+}
+
+impl Cache {
+    /// Returns the entry for `name` if it has not expired by `now`.
+    pub(crate) fn get(&self, name: &str, now: Duration) -> Option<Option<IpAddr>> {
+        self.entries
+            .get(name)
+            .and_then(|(addr, expires)| (now < *expires).then_some(*addr))
+    }
+
+    pub(crate) fn insert(&mut self, name: &str, addr: Option<IpAddr>, expires: Duration) {
+        self.entries.insert(name.to_string(), (addr, expires));
     }
 }
 
@@ -58,17 +258,16 @@ impl ToIpAddr for String {
     }
 }
 
-impl<'a> ToIpAddr for &'a str {
+impl ToIpAddr for &str {
     fn to_ip_addr(&self, dns: &mut Dns) -> IpAddr {
-        *dns.names.entry(self.to_string()).or_insert_with(|| {
-            let host = dns.next;
-            dns.next += 1;
+        if let Some(addr) = dns.names.get(*self) {
+            return *addr;
+        }
 
-            let a = (host >> 8) as u8;
-            let b = (host & 0xFF) as u8;
+        let addr = dns.assign();
+        dns.names.insert(self.to_string(), addr);
 
-            std::net::Ipv4Addr::new(192, 168, a, b).into()
-        })
+        addr
     }
 }
 
@@ -87,6 +286,18 @@ where
     }
 }
 
+impl ToIpAddrs for Vec<IpAddr> {
+    fn to_ip_addrs(&self, dns: &mut Dns) -> Vec<IpAddr> {
+        self.as_slice().to_ip_addrs(dns)
+    }
+}
+
+impl ToIpAddrs for &[IpAddr] {
+    fn to_ip_addrs(&self, _: &mut Dns) -> Vec<IpAddr> {
+        self.to_vec()
+    }
+}
+
 #[cfg(feature = "regex")]
 impl ToIpAddrs for Regex {
     fn to_ip_addrs(&self, dns: &mut Dns) -> Vec<IpAddr> {
@@ -99,14 +310,33 @@ impl ToIpAddrs for Regex {
     }
 }
 
+impl ToIpAddrs for Group<'_> {
+    fn to_ip_addrs(&self, dns: &mut Dns) -> Vec<IpAddr> {
+        let members = match dns.groups.get(self.0) {
+            Some(members) => members,
+            None => panic!("no host group found for name: {}", self.0),
+        };
+
+        // Members removed since the group was registered are skipped
+        members
+            .iter()
+            .filter_map(|m| dns.names.get(m).copied())
+            .collect()
+    }
+}
+
 // Hostname and port
 impl ToSocketAddrs for (String, u16) {
     fn to_socket_addr(&self, dns: &Dns) -> SocketAddr {
         (&self.0[..], self.1).to_socket_addr(dns)
     }
+
+    fn is_hostname(&self) -> bool {
+        (&self.0[..], self.1).is_hostname()
+    }
 }
 
-impl<'a> ToSocketAddrs for (&'a str, u16) {
+impl ToSocketAddrs for (&str, u16) {
     fn to_socket_addr(&self, dns: &Dns) -> SocketAddr {
         // When IP address is passed directly as a str.
         if let Ok(ip) = self.0.parse::<IpAddr>() {
@@ -118,6 +348,10 @@ impl<'a> ToSocketAddrs for (&'a str, u16) {
             None => panic!("no ip address found for a hostname: {}", self.0),
         }
     }
+
+    fn is_hostname(&self) -> bool {
+        self.0.parse::<IpAddr>().is_err()
+    }
 }
 
 impl ToSocketAddrs for SocketAddr {
@@ -148,6 +382,10 @@ impl<T: ToSocketAddrs + ?Sized> ToSocketAddrs for &T {
     fn to_socket_addr(&self, dns: &Dns) -> SocketAddr {
         (**self).to_socket_addr(dns)
     }
+
+    fn is_hostname(&self) -> bool {
+        (**self).is_hostname()
+    }
 }
 
 impl ToSocketAddrs for str {
@@ -175,12 +413,20 @@ impl ToSocketAddrs for str {
 
         (host, port).to_socket_addr(dns)
     }
+
+    fn is_hostname(&self) -> bool {
+        self.parse::<SocketAddr>().is_err()
+    }
 }
 
 impl ToSocketAddrs for String {
     fn to_socket_addr(&self, dns: &Dns) -> SocketAddr {
         self.as_str().to_socket_addr(dns)
     }
+
+    fn is_hostname(&self) -> bool {
+        self.as_str().is_hostname()
+    }
 }
 
 mod sealed {
@@ -192,7 +438,11 @@ mod sealed {
 
 #[cfg(test)]
 mod tests {
-    use crate::{dns::Dns, ToSocketAddrs};
+    use crate::{
+        dns::{Dns, Group},
+        ToSocketAddrs,
+    };
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 
     #[test]
     fn parse_str() {
@@ -210,4 +460,88 @@ mod tests {
         assert_eq!(ipv4_port.to_socket_addr(&dns), ipv4_port.parse().unwrap());
         assert_eq!(ipv6_port.to_socket_addr(&dns), ipv6_port.parse().unwrap());
     }
+
+    #[test]
+    fn reuse_removed_addr() {
+        let mut dns = Dns::new();
+        let foo = dns.lookup("foo");
+        let bar = dns.lookup("bar");
+
+        dns.remove(foo);
+
+        assert_eq!(foo, dns.lookup("baz"));
+        assert_ne!(bar, dns.lookup("qux"));
+    }
+
+    #[test]
+    fn reuse_removed_addr_of_same_family() {
+        let mut dns = Dns::new();
+        let v6 = IpAddr::V6(Ipv6Addr::LOCALHOST);
+        dns.insert("foo", v6);
+
+        dns.remove(v6);
+
+        assert!(dns.lookup("bar").is_ipv4());
+    }
+
+    #[test]
+    fn custom_allocator() {
+        let mut dns =
+            Dns::with_custom_allocator(|i| Ipv4Addr::new(10, 0, 0, 10 * (i as u8 + 1)).into());
+
+        let foo = dns.lookup("foo");
+        let bar = dns.lookup("bar");
+        assert_eq!(IpAddr::from(Ipv4Addr::new(10, 0, 0, 10)), foo);
+        assert_eq!(IpAddr::from(Ipv4Addr::new(10, 0, 0, 20)), bar);
+
+        dns.remove(foo);
+        assert_eq!(foo, dns.lookup("baz"));
+        assert_eq!(IpAddr::from(Ipv4Addr::new(10, 0, 0, 30)), dns.lookup("qux"));
+    }
+
+    #[test]
+    #[should_panic(expected = "allocated address 10.0.0.1 is already in use")]
+    fn custom_allocator_conflict() {
+        let mut dns = Dns::with_custom_allocator(|_| Ipv4Addr::new(10, 0, 0, 1).into());
+
+        dns.lookup("foo");
+        dns.lookup("bar");
+    }
+
+    #[test]
+    #[should_panic(expected = "allocated address 10.0.0.1 is already in use")]
+    fn custom_allocator_conflict_with_reserved() {
+        let mut dns = Dns::with_custom_allocator(|_| Ipv4Addr::new(10, 0, 0, 1).into());
+        dns.reserve(Ipv4Addr::new(10, 0, 0, 1).into());
+
+        dns.lookup("foo");
+    }
+
+    #[test]
+    #[should_panic(expected = "allocated address ::2 is not of the same family")]
+    fn custom_allocator_mixed_families() {
+        let mut dns = Dns::with_custom_allocator(|i| match i {
+            0 => Ipv4Addr::new(10, 0, 0, 1).into(),
+            _ => Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 2).into(),
+        });
+
+        dns.lookup("foo");
+        dns.lookup("bar");
+    }
+
+    #[test]
+    fn group() {
+        let mut dns = Dns::new();
+        let foo = dns.lookup("foo");
+        let bar = dns.lookup("bar");
+        dns.lookup("baz");
+
+        dns.add_group("group", &["foo", "bar"]);
+
+        assert_eq!(vec![foo, bar], dns.lookup_many(Group("group")));
+
+        dns.remove(foo);
+        assert_eq!(vec![bar], dns.lookup_many(Group("group")));
+        assert!(dns.resolve("foo").is_none());
+    }
 }
@@ -1,6 +1,7 @@
 use std::{fmt::Display, net::SocketAddr};
 
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
+use rand::{Rng, RngCore};
 use tokio::sync::oneshot;
 
 #[derive(Debug)]
@@ -19,7 +20,7 @@ pub enum Protocol {
 
 /// UDP datagram.
 #[derive(Debug)]
-pub struct Datagram(pub Bytes);
+pub struct Datagram(pub Bytes, pub(crate) u8);
 
 /// This is a simplification of real TCP.
 ///
@@ -39,6 +40,73 @@ pub struct Syn {
     pub(crate) ack: oneshot::Sender<()>,
 }
 
+impl Protocol {
+    /// The kind of message, without its payload.
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Protocol::Tcp(Segment::Syn(_)) => "TCP SYN",
+            Protocol::Tcp(Segment::Data(..)) => "TCP",
+            Protocol::Tcp(Segment::Fin(_)) => "TCP FIN",
+            Protocol::Tcp(Segment::Rst) => "TCP RST",
+            Protocol::Udp(_) => "UDP",
+        }
+    }
+
+    /// Discard any payload bytes beyond the first `len`.
+    pub(crate) fn truncate(&mut self, len: usize) {
+        match self {
+            Protocol::Tcp(Segment::Data(_, bytes)) | Protocol::Udp(Datagram(bytes, _)) => {
+                bytes.truncate(len)
+            }
+            Protocol::Tcp(_) => {}
+        }
+    }
+
+    /// Flip a random bit in each of up to `max_bytes` randomly chosen payload
+    /// bytes. Returns the number of bytes corrupted.
+    pub(crate) fn corrupt(&mut self, rand: &mut dyn RngCore, max_bytes: usize) -> usize {
+        let bytes = match self {
+            Protocol::Tcp(Segment::Data(_, bytes)) | Protocol::Udp(Datagram(bytes, _)) => bytes,
+            Protocol::Tcp(_) => return 0,
+        };
+
+        let max_bytes = max_bytes.min(bytes.len());
+        if max_bytes == 0 {
+            return 0;
+        }
+
+        let count = rand.gen_range(1..=max_bytes);
+        let mut corrupted = BytesMut::from(&bytes[..]);
+
+        for i in rand::seq::index::sample(rand, corrupted.len(), count) {
+            corrupted[i] ^= 1 << rand.gen_range(0..8);
+        }
+
+        *bytes = corrupted.freeze();
+        count
+    }
+
+    /// The payload bytes carried by the message, empty for TCP control
+    /// segments.
+    pub(crate) fn payload(&self) -> Bytes {
+        match self {
+            Protocol::Tcp(Segment::Data(_, bytes)) | Protocol::Udp(Datagram(bytes, _)) => {
+                bytes.clone()
+            }
+            Protocol::Tcp(_) => Bytes::new(),
+        }
+    }
+
+    /// The number of payload bytes carried by the message.
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            Protocol::Tcp(Segment::Data(_, bytes)) => bytes.len(),
+            Protocol::Udp(Datagram(bytes, _)) => bytes.len(),
+            Protocol::Tcp(_) => 0,
+        }
+    }
+}
+
 impl Display for Protocol {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -48,6 +116,14 @@ impl Display for Protocol {
     }
 }
 
+impl Datagram {
+    /// The IP TOS byte the datagram was sent with, see
+    /// [`UdpSocket::set_tos`](crate::net::UdpSocket::set_tos).
+    pub fn tos(&self) -> u8 {
+        self.1
+    }
+}
+
 impl Display for Datagram {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         hex("UDP", &self.0, f)
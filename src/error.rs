@@ -1,3 +1,6 @@
+use std::fmt::Display;
+use std::time::Duration;
+
 /// A specialized [`Result`] type for turmoil simulations.
 ///
 /// This type is generally useful for fallible test cases, i.e. where you want
@@ -6,3 +9,43 @@
 ///
 /// [`Result`]: std::result::Result
 pub type Result<T = ()> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// Returned when a condition is not met within a timeout in simulated time.
+#[derive(Debug)]
+pub struct TimedOut {
+    pub(crate) timeout: Duration,
+}
+
+impl TimedOut {
+    /// The timeout that elapsed.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+}
+
+impl Display for TimedOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "timed out after {:?}", self.timeout)
+    }
+}
+
+impl std::error::Error for TimedOut {}
+
+/// The failure of a single host's software, collected by
+/// [`Sim::run_until_all_hosts_complete`](crate::Sim::run_until_all_hosts_complete).
+#[derive(Debug)]
+pub struct HostError {
+    /// The hostname, or ip address if the host was registered without one.
+    pub host: String,
+
+    /// The error returned by the software, or the message it panicked with.
+    pub error: Box<dyn std::error::Error>,
+}
+
+impl Display for HostError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.host, self.error)
+    }
+}
+
+impl std::error::Error for HostError {}
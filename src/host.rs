@@ -1,18 +1,25 @@
+use crate::dns;
 use crate::envelope::{hex, Datagram, Protocol, Segment, Syn};
-use crate::net::{SocketPair, TcpListener, UdpSocket};
+use crate::net::{RecvBuffer, SocketPair, TcpListener, UdpSocket};
 use crate::world::World;
 use crate::{Envelope, TRACING_TARGET};
 
 use bytes::Bytes;
-use indexmap::IndexMap;
+use indexmap::{IndexMap, IndexSet};
 use std::collections::VecDeque;
 use std::fmt::Display;
 use std::io;
-use std::net::{IpAddr, SocketAddr};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::sync::Arc;
 use tokio::sync::{mpsc, Notify};
 use tokio::time::{Duration, Instant};
 
+/// The default limit on sockets a host may have open at once.
+const DEFAULT_MAX_OPEN_FILES: usize = 65535;
+
+/// "Too many open files", as on Linux and macOS.
+const EMFILE: i32 = 24;
+
 /// A host in the simulated network.
 ///
 /// Hosts have [`Udp`] and [`Tcp`] software available for networking.
@@ -22,6 +29,9 @@ pub(crate) struct Host {
     /// Host ip address.
     pub(crate) addr: IpAddr,
 
+    /// Additional ip addresses the host is reachable at.
+    pub(crate) interfaces: Vec<IpAddr>,
+
     /// L4 User Datagram Protocol (UDP).
     pub(crate) udp: Udp,
 
@@ -31,6 +41,20 @@ pub(crate) struct Host {
     /// Ports 1024..=65535 for client connections.
     next_ephemeral_port: u16,
 
+    /// Results of [`crate::resolve_async`] made by the host.
+    pub(crate) dns_cache: dns::Cache,
+
+    /// Messages appended with [`crate::Sim::host_log`].
+    pub(crate) log: Vec<(Duration, String)>,
+
+    /// Maximum number of sockets open at once, see
+    /// [`crate::Sim::set_max_open_files`].
+    pub(crate) max_open_files: usize,
+
+    /// Maximum number of received messages buffered for the software, see
+    /// [`crate::Sim::set_host_rx_queue_depth`].
+    pub(crate) rx_queue_depth: Option<usize>,
+
     /// Host elapsed time.
     elapsed: Duration,
 
@@ -42,9 +66,14 @@ impl Host {
     pub(crate) fn new(addr: IpAddr) -> Host {
         Host {
             addr,
+            interfaces: vec![],
             udp: Udp::new(),
             tcp: Tcp::new(),
             next_ephemeral_port: 1024,
+            dns_cache: dns::Cache::default(),
+            log: vec![],
+            max_open_files: DEFAULT_MAX_OPEN_FILES,
+            rx_queue_depth: None,
             elapsed: Duration::ZERO,
             now: None,
         }
@@ -67,6 +96,64 @@ impl Host {
         self.elapsed + run_duration
     }
 
+    /// Whether `addr` is one of the host's addresses.
+    pub(crate) fn has_addr(&self, addr: IpAddr) -> bool {
+        self.addr == addr || self.interfaces.contains(&addr)
+    }
+
+    /// The host's IPv6 and IPv4 addresses, if it has one of each.
+    pub(crate) fn dual_stack_addrs(&self) -> Option<(IpAddr, IpAddr)> {
+        let mut addrs = std::iter::once(&self.addr).chain(&self.interfaces);
+        let v6 = addrs.clone().find(|addr| addr.is_ipv6())?;
+        let v4 = addrs.find(|addr| addr.is_ipv4())?;
+
+        Some((*v6, *v4))
+    }
+
+    /// The address to send from to reach `dst`, which is the primary address
+    /// unless it is of a different IP version than `dst`.
+    pub(crate) fn source_addr(&self, dst: IpAddr) -> Option<IpAddr> {
+        std::iter::once(&self.addr)
+            .chain(&self.interfaces)
+            .find(|addr| addr.is_ipv4() == dst.is_ipv4())
+            .copied()
+    }
+
+    /// The address to bind a socket at `addr` to.
+    ///
+    /// The unspecified address binds to the host's primary address, except for
+    /// `::` which binds to its IPv6 address should the primary address be
+    /// IPv4. Panics if the address does not belong to the host.
+    pub(crate) fn bind_addr(&self, mut addr: SocketAddr) -> SocketAddr {
+        if addr.ip().is_unspecified() {
+            let ip = match addr {
+                SocketAddr::V6(_) => self.source_addr(addr.ip()).unwrap_or(self.addr),
+                SocketAddr::V4(_) => self.addr,
+            };
+
+            addr.set_ip(ip);
+        }
+
+        if !self.has_addr(addr.ip()) {
+            panic!("{addr} is not supported");
+        }
+
+        addr
+    }
+
+    /// Fails with `EMFILE` if opening another socket would exceed
+    /// `max_open_files`. UDP sockets, TCP listeners and TCP streams all count
+    /// towards the limit.
+    pub(crate) fn check_open_files(&self) -> io::Result<()> {
+        let open = self.udp.binds.len() + self.tcp.binds.len() + self.tcp.sockets.len();
+
+        if open >= self.max_open_files {
+            return Err(io::Error::from_raw_os_error(EMFILE));
+        }
+
+        Ok(())
+    }
+
     pub(crate) fn assign_ephemeral_port(&mut self) -> u16 {
         // Check for existing binds to avoid port conflicts
         loop {
@@ -100,10 +187,19 @@ impl Host {
 
         tracing::trace!(target: TRACING_TARGET, ?dst, ?src, protocol = %message, "Delivered");
 
+        // Sockets bound to the unspecified address receive messages sent to
+        // any of the host's addresses. Binds to `::` are preferred for IPv6
+        // messages, and binds to the primary address (0.0.0.0) receive the
+        // rest.
+        let fallbacks = [
+            SocketAddr::new(self.source_addr(dst.ip()).unwrap_or(self.addr), dst.port()),
+            SocketAddr::new(self.addr, dst.port()),
+        ];
+
         match message {
-            Protocol::Tcp(segment) => self.tcp.receive_from_network(src, dst, segment),
+            Protocol::Tcp(segment) => self.tcp.receive_from_network(src, dst, fallbacks, segment),
             Protocol::Udp(datagram) => {
-                self.udp.receive_from_network(src, dst, datagram);
+                self.udp.receive_from_network(src, dst, fallbacks, datagram);
                 Ok(())
             }
         }
@@ -112,6 +208,118 @@ impl Host {
     pub(crate) fn tick(&mut self, duration: Duration) {
         self.elapsed += duration
     }
+
+    /// The number of messages delivered to the host that its software has not
+    /// yet received, such as unread datagrams or unaccepted connections.
+    pub(crate) fn buffered_messages(&self) -> usize {
+        let udp: usize = self.udp.binds.values().map(|b| queued(&b.sender)).sum();
+        let syns: usize = self.tcp.binds.values().map(|b| b.deque.len()).sum();
+        let segments: usize = self
+            .tcp
+            .sockets
+            .values()
+            .map(|s| s.buf.len() + queued(&s.sender))
+            .sum();
+
+        udp + syns + segments
+    }
+
+    /// Whether `message` should be dropped because the receive queue is full.
+    ///
+    /// Only datagrams and new connections are dropped, as the simulated TCP
+    /// does not retransmit segments of established streams.
+    pub(crate) fn is_rx_queue_full(&self, message: &Protocol) -> bool {
+        let Some(depth) = self.rx_queue_depth else {
+            return false;
+        };
+
+        let is_droppable = matches!(message, Protocol::Udp(_) | Protocol::Tcp(Segment::Syn(_)));
+
+        is_droppable && self.buffered_messages() >= depth
+    }
+
+    pub(crate) fn snapshot(&self, is_running: bool) -> HostSnapshot {
+        HostSnapshot {
+            addr: self.addr,
+            is_running,
+            elapsed: self.elapsed,
+            udp_binds: self.udp.binds.keys().copied().collect(),
+            tcp_binds: self.tcp.binds.keys().copied().collect(),
+            tcp_connections: self
+                .tcp
+                .sockets
+                .keys()
+                .map(|pair| (pair.local, pair.remote))
+                .collect(),
+        }
+    }
+}
+
+/// An established TCP connection, see
+/// [`Sim::host_tcp_connections`](crate::Sim::host_tcp_connections).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionInfo {
+    /// The host's address of the connection.
+    pub local_addr: SocketAddr,
+
+    /// The peer's address of the connection.
+    pub peer_addr: SocketAddr,
+
+    /// Payload bytes written to the connection by the host.
+    pub bytes_sent: u64,
+
+    /// Payload bytes received by the host from the network, whether or not
+    /// its software has read them yet.
+    pub bytes_received: u64,
+
+    /// When the connection was established, in simulated time since the
+    /// simulation started.
+    pub established_at: Duration,
+}
+
+/// A point in time view of a host's state, taken in between simulation steps.
+///
+/// See [`Sim::wait_for`](crate::Sim::wait_for).
+#[derive(Debug, Clone)]
+pub struct HostSnapshot {
+    addr: IpAddr,
+    is_running: bool,
+    elapsed: Duration,
+    udp_binds: Vec<SocketAddr>,
+    tcp_binds: Vec<SocketAddr>,
+    tcp_connections: Vec<(SocketAddr, SocketAddr)>,
+}
+
+impl HostSnapshot {
+    /// The host's ip address.
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    /// Whether the host has software running.
+    pub fn is_running(&self) -> bool {
+        self.is_running
+    }
+
+    /// How long the host has been executing for in virtual time.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Addresses of bound UDP sockets.
+    pub fn udp_binds(&self) -> &[SocketAddr] {
+        &self.udp_binds
+    }
+
+    /// Addresses of bound TCP listeners.
+    pub fn tcp_binds(&self) -> &[SocketAddr] {
+        &self.tcp_binds
+    }
+
+    /// The (local, remote) address pairs of open TCP streams.
+    pub fn tcp_connections(&self) -> &[(SocketAddr, SocketAddr)] {
+        &self.tcp_connections
+    }
 }
 
 /// Returns how long the currently executing host has been executing for in
@@ -122,10 +330,14 @@ pub fn elapsed() -> Duration {
     World::current(|world| world.current_host_mut().elapsed())
 }
 
+fn queued<T>(sender: &mpsc::Sender<T>) -> usize {
+    sender.max_capacity() - sender.capacity()
+}
+
 /// Simulated UDP host software.
 pub(crate) struct Udp {
     /// Bound udp sockets
-    binds: IndexMap<SocketAddr, mpsc::Sender<(Datagram, SocketAddr)>>,
+    binds: IndexMap<SocketAddr, UdpBind>,
 
     /// UdpSocket channel capacity
     capacity: usize,
@@ -146,31 +358,106 @@ impl Udp {
 
     pub(crate) fn bind(&mut self, addr: SocketAddr) -> io::Result<UdpSocket> {
         let (tx, rx) = mpsc::channel(self.capacity);
+        let recv_buffer = Arc::new(RecvBuffer::new());
 
-        if self.binds.insert(addr, tx).is_some() {
+        let bind = UdpBind {
+            sender: tx,
+            recv_buffer: recv_buffer.clone(),
+            multicast_groups: IndexSet::new(),
+        };
+
+        if self.binds.insert(addr, bind).is_some() {
             return Err(io::Error::new(io::ErrorKind::AddrInUse, addr.to_string()));
         }
 
         tracing::info!(target: TRACING_TARGET, ?addr, protocol = %"UDP", "Bind");
 
-        Ok(UdpSocket::new(addr, rx))
+        Ok(UdpSocket::new(addr, rx, recv_buffer))
     }
 
-    fn receive_from_network(&mut self, src: SocketAddr, dst: SocketAddr, datagram: Datagram) {
-        if let Some(s) = self.binds.get_mut(&dst) {
-            if let Err(err) = s.try_send((datagram, src)) {
-                // drop any packets that exceed the capacity
-                // TODO: ideally we should drop the oldest packets instead of new ones, but this would
-                //       require a different channel implementation.
-                match err {
-                    mpsc::error::TrySendError::Full((datagram, _)) => {
-                        tracing::trace!(target: TRACING_TARGET, ?dst, ?src, protocol = %Protocol::Udp(datagram), "Dropped (Full buffer)");
-                    }
-                    mpsc::error::TrySendError::Closed((datagram, _)) => {
-                        tracing::trace!(target: TRACING_TARGET, ?dst, ?src, protocol = %Protocol::Udp(datagram), "Dropped (Receiver closed)");
-                    }
-                }
+    /// Join the socket bound to `addr` to the multicast `group`.
+    pub(crate) fn join_multicast_v4(
+        &mut self,
+        addr: SocketAddr,
+        group: Ipv4Addr,
+    ) -> io::Result<()> {
+        if !group.is_multicast() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{group} is not a multicast address"),
+            ));
+        }
+
+        let bind = self.binds.get_mut(&addr).expect("unknown bind");
+        if !bind.multicast_groups.insert(group) {
+            return Err(io::Error::new(io::ErrorKind::AddrInUse, group.to_string()));
+        }
+
+        tracing::info!(target: TRACING_TARGET, ?addr, ?group, protocol = %"UDP", "Join");
+
+        Ok(())
+    }
+
+    /// Remove the socket bound to `addr` from the multicast `group`.
+    pub(crate) fn leave_multicast_v4(
+        &mut self,
+        addr: SocketAddr,
+        group: Ipv4Addr,
+    ) -> io::Result<()> {
+        let bind = self.binds.get_mut(&addr).expect("unknown bind");
+        if !bind.multicast_groups.shift_remove(&group) {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrNotAvailable,
+                group.to_string(),
+            ));
+        }
+
+        tracing::info!(target: TRACING_TARGET, ?addr, ?group, protocol = %"UDP", "Leave");
+
+        Ok(())
+    }
+
+    /// Whether any socket is joined to the multicast group at `group`'s ip
+    /// and bound to its port.
+    pub(crate) fn is_multicast_member(&self, group: SocketAddr) -> bool {
+        self.multicast_members(group).next().is_some()
+    }
+
+    fn multicast_members(&self, group: SocketAddr) -> impl Iterator<Item = SocketAddr> + '_ {
+        self.binds
+            .iter()
+            .filter(move |(addr, bind)| {
+                addr.port() == group.port()
+                    && matches!(group.ip(), IpAddr::V4(ip) if bind.multicast_groups.contains(&ip))
+            })
+            .map(|(addr, _)| *addr)
+    }
+
+    fn receive_from_network(
+        &mut self,
+        src: SocketAddr,
+        dst: SocketAddr,
+        fallbacks: [SocketAddr; 2],
+        datagram: Datagram,
+    ) {
+        // Multicast datagrams are received by each socket joined to the group
+        if dst.ip().is_multicast() {
+            let members: Vec<_> = self.multicast_members(dst).collect();
+            for member in members {
+                let copy = Datagram(datagram.0.clone(), datagram.1);
+                self.binds[&member].deliver(src, dst, copy);
             }
+
+            return;
+        }
+
+        let bind = std::iter::once(dst)
+            .chain(fallbacks)
+            .find(|addr| self.binds.contains_key(addr))
+            .unwrap_or(dst);
+
+        if let Some(s) = self.binds.get(&bind) {
+            s.deliver(src, dst, datagram);
         }
     }
 
@@ -183,6 +470,41 @@ impl Udp {
     }
 }
 
+struct UdpBind {
+    sender: mpsc::Sender<(Datagram, SocketAddr, SocketAddr)>,
+    recv_buffer: Arc<RecvBuffer>,
+    /// Multicast groups the socket has joined.
+    multicast_groups: IndexSet<Ipv4Addr>,
+}
+
+impl UdpBind {
+    fn deliver(&self, src: SocketAddr, dst: SocketAddr, datagram: Datagram) {
+        let len = datagram.0.len();
+
+        // drop-tail once the receive buffer is full
+        if !self.recv_buffer.reserve(len) {
+            tracing::trace!(target: TRACING_TARGET, ?dst, ?src, protocol = %Protocol::Udp(datagram), "Dropped (Full receive buffer)");
+            return;
+        }
+
+        if let Err(err) = self.sender.try_send((datagram, src, dst)) {
+            self.recv_buffer.release(len);
+
+            // drop any packets that exceed the capacity
+            // TODO: ideally we should drop the oldest packets instead of new ones, but this would
+            //       require a different channel implementation.
+            match err {
+                mpsc::error::TrySendError::Full((datagram, ..)) => {
+                    tracing::trace!(target: TRACING_TARGET, ?dst, ?src, protocol = %Protocol::Udp(datagram), "Dropped (Full buffer)");
+                }
+                mpsc::error::TrySendError::Closed((datagram, ..)) => {
+                    tracing::trace!(target: TRACING_TARGET, ?dst, ?src, protocol = %Protocol::Udp(datagram), "Dropped (Receiver closed)");
+                }
+            }
+        }
+    }
+}
+
 pub(crate) struct Tcp {
     /// Bound server sockets
     binds: IndexMap<SocketAddr, ServerSocket>,
@@ -193,20 +515,32 @@ pub(crate) struct Tcp {
     /// Active stream sockets
     sockets: IndexMap<SocketPair, StreamSocket>,
 
+    /// Pending errors for streams that have been reset, see
+    /// [`TcpStream::take_error`](crate::net::TcpStream::take_error).
+    errors: IndexMap<SocketPair, io::ErrorKind>,
+
     /// TcpStream channel capacity
     socket_capacity: usize,
+
+    /// Maximum number of open and pending connections, see
+    /// [`Sim::set_connection_limit`](crate::Sim::set_connection_limit).
+    max_connections: Option<usize>,
 }
 
 struct ServerSocket {
     /// Notify the TcpListener when SYNs are delivered
     notify: Arc<Notify>,
 
-    /// Pending connections for the TcpListener to accept
-    deque: VecDeque<(Syn, SocketAddr)>,
+    /// Pending connections for the TcpListener to accept, as (syn, origin,
+    /// local address the syn was delivered to)
+    deque: VecDeque<(Syn, SocketAddr, SocketAddr)>,
 }
 
 struct StreamSocket {
     local_addr: SocketAddr,
+    /// Whether the stream was accepted by a listener, as opposed to connected
+    /// from this host.
+    accepted: bool,
     buf: IndexMap<u64, SequencedSegment>,
     next_send_seq: u64,
     recv_seq: u64,
@@ -214,6 +548,13 @@ struct StreamSocket {
     /// A simple reference counter for tracking read/write half drops. Once 0, the
     /// socket may be removed from the host.
     ref_ct: usize,
+    /// Payload bytes written to the stream.
+    bytes_sent: u64,
+    /// Payload bytes received from the network.
+    bytes_received: u64,
+    /// When the connection was established, in simulated time. Not set while
+    /// connecting.
+    established_at: Option<Duration>,
 }
 
 /// Stripped down version of [`Segment`] for delivery out to the application
@@ -234,15 +575,23 @@ impl Display for SequencedSegment {
 }
 
 impl StreamSocket {
-    fn new(local_addr: SocketAddr, capacity: usize) -> (Self, mpsc::Receiver<SequencedSegment>) {
+    fn new(
+        local_addr: SocketAddr,
+        accepted: bool,
+        capacity: usize,
+    ) -> (Self, mpsc::Receiver<SequencedSegment>) {
         let (tx, rx) = mpsc::channel(capacity);
         let sock = Self {
             local_addr,
+            accepted,
             buf: IndexMap::new(),
             next_send_seq: 1,
             recv_seq: 0,
             sender: tx,
             ref_ct: 2,
+            bytes_sent: 0,
+            bytes_received: 0,
+            established_at: None,
         };
 
         (sock, rx)
@@ -282,17 +631,35 @@ impl Tcp {
         Self {
             binds: IndexMap::new(),
             sockets: IndexMap::new(),
+            errors: IndexMap::new(),
             // TODO: Make capacity configurable
             server_socket_capacity: 64,
             socket_capacity: 64,
+            max_connections: None,
         }
     }
 
-    fn is_port_assigned(&self, port: u16) -> bool {
+    pub(crate) fn is_port_assigned(&self, port: u16) -> bool {
         self.binds.keys().any(|a| a.port() == port)
             || self.sockets.keys().any(|a| a.local.port() == port)
     }
 
+    pub(crate) fn set_max_connections(&mut self, value: usize) {
+        self.max_connections = Some(value);
+    }
+
+    /// Whether the connection limit is reached, counting open streams and
+    /// connections waiting to be accepted.
+    pub(crate) fn is_at_connection_limit(&self) -> bool {
+        let Some(max) = self.max_connections else {
+            return false;
+        };
+
+        let pending: usize = self.binds.values().map(|b| b.deque.len()).sum();
+
+        self.sockets.len() + pending >= max
+    }
+
     pub(crate) fn bind(&mut self, addr: SocketAddr) -> io::Result<TcpListener> {
         let notify = Arc::new(Notify::new());
         let sock = ServerSocket {
@@ -309,17 +676,73 @@ impl Tcp {
         Ok(TcpListener::new(addr, notify))
     }
 
-    pub(crate) fn new_stream(&mut self, pair: SocketPair) -> mpsc::Receiver<SequencedSegment> {
-        let (sock, rx) = StreamSocket::new(pair.local, self.socket_capacity);
+    /// Create a stream for `pair`, which was either `accepted` by a listener or
+    /// connected from this host. Connected streams are established once the
+    /// handshake completes, see [`Tcp::establish_stream`].
+    pub(crate) fn new_stream(
+        &mut self,
+        pair: SocketPair,
+        accepted: bool,
+        established_at: Option<Duration>,
+    ) -> mpsc::Receiver<SequencedSegment> {
+        let (mut sock, rx) = StreamSocket::new(pair.local, accepted, self.socket_capacity);
+        sock.established_at = established_at;
 
         let exists = self.sockets.insert(pair, sock);
 
         assert!(exists.is_none(), "{pair:?} is already connected");
 
+        self.errors.remove(&pair);
+
         rx
     }
 
-    pub(crate) fn accept(&mut self, addr: SocketAddr) -> Option<(Syn, SocketAddr)> {
+    /// Whether a listener on this host has accepted a stream from a peer
+    /// address matching `is_peer`.
+    pub(crate) fn has_accepted(&self, is_peer: impl Fn(IpAddr) -> bool) -> bool {
+        self.sockets
+            .iter()
+            .any(|(pair, sock)| sock.accepted && is_peer(pair.remote.ip()))
+    }
+
+    /// Mark a stream connected from this host as established at `now`.
+    pub(crate) fn establish_stream(&mut self, pair: SocketPair, now: Duration) {
+        if let Some(sock) = self.sockets.get_mut(&pair) {
+            sock.established_at = Some(now);
+        }
+    }
+
+    /// Established streams, see
+    /// [`Sim::host_tcp_connections`](crate::Sim::host_tcp_connections).
+    pub(crate) fn connections(&self) -> Vec<ConnectionInfo> {
+        self.sockets
+            .iter()
+            .filter_map(|(pair, sock)| {
+                Some(ConnectionInfo {
+                    local_addr: pair.local,
+                    peer_addr: pair.remote,
+                    bytes_sent: sock.bytes_sent,
+                    bytes_received: sock.bytes_received,
+                    established_at: sock.established_at?,
+                })
+            })
+            .collect()
+    }
+
+    /// The number of open streams, as (accepted by a listener, connected from
+    /// this host).
+    pub(crate) fn connection_counts(&self) -> (usize, usize) {
+        let accepted = self.sockets.values().filter(|s| s.accepted).count();
+
+        (accepted, self.sockets.len() - accepted)
+    }
+
+    /// Remove a stream that failed to connect.
+    pub(crate) fn remove_stream(&mut self, pair: SocketPair) {
+        self.sockets.remove(&pair);
+    }
+
+    pub(crate) fn accept(&mut self, addr: SocketAddr) -> Option<(Syn, SocketAddr, SocketAddr)> {
         self.binds[&addr].deque.pop_front()
     }
 
@@ -330,27 +753,48 @@ impl Tcp {
         Some(sock.assign_seq())
     }
 
+    /// Count `len` payload bytes written to the stream for `pair`.
+    pub(crate) fn record_sent(&mut self, pair: SocketPair, len: usize) {
+        if let Some(sock) = self.sockets.get_mut(&pair) {
+            sock.bytes_sent += len as u64;
+        }
+    }
+
     fn receive_from_network(
         &mut self,
         src: SocketAddr,
         dst: SocketAddr,
+        fallbacks: [SocketAddr; 2],
         segment: Segment,
     ) -> Result<(), Protocol> {
         match segment {
             Segment::Syn(syn) => {
+                let bind = std::iter::once(dst)
+                    .chain(fallbacks)
+                    .find(|addr| self.binds.contains_key(addr))
+                    .unwrap_or(dst);
+
                 // If bound, queue the syn; else we drop the syn triggering
                 // connection refused on the client.
-                if let Some(b) = self.binds.get_mut(&dst) {
+                if self.is_at_connection_limit() {
+                    tracing::trace!(target: TRACING_TARGET, ?dst, ?src, protocol = %"TCP SYN", "Dropped (Connection limit)");
+                    return Ok(());
+                }
+
+                if let Some(b) = self.binds.get_mut(&bind) {
                     if b.deque.len() == self.server_socket_capacity {
                         todo!("{} server socket buffer full", dst);
                     }
 
-                    b.deque.push_back((syn, src));
+                    b.deque.push_back((syn, src, dst));
                     b.notify.notify_one();
                 }
             }
             Segment::Data(seq, data) => match self.sockets.get_mut(&SocketPair::new(dst, src)) {
-                Some(sock) => sock.buffer(seq, SequencedSegment::Data(data))?,
+                Some(sock) => {
+                    sock.bytes_received += data.len() as u64;
+                    sock.buffer(seq, SequencedSegment::Data(data))?
+                }
                 None => return Err(Protocol::Tcp(Segment::Rst)),
             },
             Segment::Fin(seq) => match self.sockets.get_mut(&SocketPair::new(dst, src)) {
@@ -358,8 +802,9 @@ impl Tcp {
                 None => return Err(Protocol::Tcp(Segment::Rst)),
             },
             Segment::Rst => {
-                if self.sockets.get(&SocketPair::new(dst, src)).is_some() {
-                    self.sockets.remove(&SocketPair::new(dst, src)).unwrap();
+                let pair = SocketPair::new(dst, src);
+                if self.sockets.remove(&pair).is_some() {
+                    self.errors.insert(pair, io::ErrorKind::ConnectionReset);
                 }
             }
         };
@@ -376,9 +821,18 @@ impl Tcp {
             if sock.ref_ct == 0 {
                 self.sockets.remove(&pair).unwrap();
             }
+        } else {
+            self.errors.remove(&pair);
         }
     }
 
+    /// Take the pending error for the stream, if any.
+    pub(crate) fn take_error(&mut self, pair: SocketPair) -> Option<io::Error> {
+        self.errors
+            .remove(&pair)
+            .map(|kind| io::Error::new(kind, "Connection reset by peer"))
+    }
+
     pub(crate) fn unbind(&mut self, addr: SocketAddr) {
         let exists = self.binds.remove(&addr);
 
@@ -386,6 +840,32 @@ impl Tcp {
 
         tracing::info!(target: TRACING_TARGET, ?addr, protocol = %"TCP", "Unbind");
     }
+
+    /// Reset the stream for `pair`, returning whether it was open.
+    pub(crate) fn reset_stream(&mut self, pair: SocketPair) -> bool {
+        let is_reset = self.sockets.remove(&pair).is_some();
+        if is_reset {
+            self.errors.insert(pair, io::ErrorKind::ConnectionReset);
+        }
+
+        is_reset
+    }
+
+    /// Reset all streams connected to the `remote` host.
+    ///
+    /// Removing the socket closes the channel, which surfaces as a connection
+    /// reset to the application.
+    pub(crate) fn reset_streams(&mut self, remote: IpAddr) {
+        let errors = &mut self.errors;
+
+        self.sockets.retain(|pair, _| {
+            let is_reset = pair.remote.ip() == remote;
+            if is_reset {
+                errors.insert(*pair, io::ErrorKind::ConnectionReset);
+            }
+            !is_reset
+        });
+    }
 }
 
 #[cfg(test)]
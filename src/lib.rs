@@ -57,6 +57,16 @@
 //!   available for introspection using [`Sim`]'s `links` method.
 //! * [`release`], which releases all "in flight" messages between hosts
 //!
+//! Faults may also be injected randomly while stepping the simulation, see
+//! [`chaos::ChaosMonkey`].
+//!
+//! # Property Testing
+//!
+//! [`property::run_property_test`] runs a simulation once per seed, reporting
+//! the seed of any failing run so it can be reproduced.
+//! [`property::network_partition_sequence`] generates reproducible partition
+//! patterns to apply to a simulation.
+//!
 //! # Tracing
 //!
 //! The `tracing` crate is used to emit important events during the lifetime of
@@ -82,39 +92,49 @@ mod readme;
 
 mod builder;
 
+pub mod chaos;
+
 use std::net::IpAddr;
 
 pub use builder::Builder;
 
 mod config;
 use config::Config;
+pub use config::FlakyLinkConfig;
 
 mod dns;
-use dns::Dns;
-pub use dns::{ToIpAddr, ToIpAddrs, ToSocketAddrs};
+pub use dns::{Dns, Group, ToIpAddr, ToIpAddrs, ToSocketAddrs};
 
 mod envelope;
 use envelope::Envelope;
 pub use envelope::{Datagram, Protocol, Segment};
 
 mod error;
-pub use error::Result;
+pub use error::{HostError, Result, TimedOut};
 
 mod host;
-pub use host::elapsed;
 use host::Host;
+pub use host::{elapsed, ConnectionInfo, HostSnapshot};
 
 pub mod net;
 
+pub mod property;
+
+mod route;
+pub use route::{IpNet, Route};
+
 mod rt;
 use rt::Rt;
 
 mod sim;
-pub use sim::Sim;
+pub use sim::{EpochResult, Sim, SimHarness};
 
 mod top;
 use top::Topology;
-pub use top::{LinkIter, LinksIter, SentRef};
+pub use top::{
+    CapturedPacket, LinkIter, LinkRecorder, LinksIter, MessageRecord, SentRef, TimelineEvent,
+    TimelineEventKind,
+};
 
 mod world;
 use world::World;
@@ -138,6 +158,37 @@ pub fn lookup(addr: impl ToIpAddr) -> IpAddr {
     World::current(|world| world.lookup(addr))
 }
 
+/// Resolve a host name to an IP address, as a DNS resolver would.
+///
+/// Unlike [`lookup`], unknown names fail with [`std::io::ErrorKind::NotFound`]
+/// rather than being assigned an address, and resolving takes
+/// [`Builder::dns_latency`] of simulated time. Results, including failures,
+/// are cached by the calling host for [`Builder::dns_ttl`], during which they
+/// resolve immediately.
+///
+/// Must be called from within a Turmoil simulation.
+pub async fn resolve_async(name: &str) -> std::io::Result<IpAddr> {
+    let addr = match World::current(|world| world.resolve_cached(name)) {
+        Some(addr) => addr,
+        None => {
+            let latency = World::current(|world| world.dns.config.latency);
+
+            if !latency.is_zero() {
+                tokio::time::sleep(latency).await;
+            }
+
+            World::current(|world| world.resolve(name))
+        }
+    };
+
+    addr.ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("no host found for name: {name}"),
+        )
+    })
+}
+
 /// Lookup an IP address by host name. Use regex to match a number of hosts.
 ///
 /// Must be called from within a Turmoil simulation.
@@ -3,13 +3,20 @@
 //! They mirror [tokio::net](https://docs.rs/tokio/latest/tokio/net/) to provide
 //! a high fidelity implementation.
 
-use std::net::SocketAddr;
+use std::{fmt, net::SocketAddr};
 
 pub mod tcp;
-pub use tcp::{listener::TcpListener, stream::TcpStream};
+pub use tcp::{
+    listener::TcpListener,
+    stream::{ConnectOptions, ReadyGuard, TcpStream},
+};
 
 mod udp;
-pub use udp::UdpSocket;
+pub(crate) use udp::RecvBuffer;
+pub use udp::{UdpRecvHalf, UdpSendHalf, UdpSocket};
+
+mod pipe;
+pub use pipe::{Pipe, PipeReader, PipeWriter};
 
 #[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
 pub(crate) struct SocketPair {
@@ -23,3 +30,24 @@ impl SocketPair {
         SocketPair { local, remote }
     }
 }
+
+impl fmt::Display for SocketPair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} <-> {}", self.local, self.remote)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SocketPair;
+
+    #[test]
+    fn display_socket_pair() {
+        let pair = SocketPair::new(
+            "192.168.0.1:8080".parse().unwrap(),
+            "192.168.0.2:9090".parse().unwrap(),
+        );
+
+        assert_eq!("192.168.0.1:8080 <-> 192.168.0.2:9090", pair.to_string());
+    }
+}
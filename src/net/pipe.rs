@@ -0,0 +1,145 @@
+use bytes::{Buf, Bytes};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    sync::mpsc,
+};
+
+use std::{
+    cmp,
+    io::{self, Result},
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+/// A unidirectional, in-process byte stream between tasks on the same host.
+///
+/// Unlike [`TcpStream`](crate::net::TcpStream), a pipe does not go through
+/// the simulated network. It models loopback: writes are immediately available
+/// to the reader and are never subject to latency or fault injection.
+///
+/// Pipes may also be created outside of a simulation.
+#[derive(Debug)]
+pub struct Pipe;
+
+/// The read half of a [`Pipe`].
+#[derive(Debug)]
+pub struct PipeReader {
+    recv: mpsc::UnboundedReceiver<Bytes>,
+    /// The remaining bytes of a received write, stashed for subsequent reads.
+    buffer: Bytes,
+}
+
+/// The write half of a [`Pipe`].
+///
+/// Dropping or shutting down the writer signals EOF to the reader.
+#[derive(Debug)]
+pub struct PipeWriter {
+    send: Option<mpsc::UnboundedSender<Bytes>>,
+}
+
+impl Pipe {
+    /// Create a new pipe, returning its read and write halves.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new() -> (PipeReader, PipeWriter) {
+        let (send, recv) = mpsc::unbounded_channel();
+
+        (
+            PipeReader {
+                recv,
+                buffer: Bytes::new(),
+            },
+            PipeWriter { send: Some(send) },
+        )
+    }
+}
+
+impl AsyncRead for PipeReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf,
+    ) -> Poll<Result<()>> {
+        if buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        if self.buffer.is_empty() {
+            match ready!(self.recv.poll_recv(cx)) {
+                Some(bytes) => self.buffer = bytes,
+                // The writer has gone away, EOF
+                None => return Poll::Ready(Ok(())),
+            }
+        }
+
+        let amt = cmp::min(self.buffer.len(), buf.remaining());
+        buf.put_slice(&self.buffer[..amt]);
+        self.buffer.advance(amt);
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for PipeWriter {
+    fn poll_write(self: Pin<&mut Self>, _cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        let Some(send) = &self.send else {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "Pipe is shut down",
+            )));
+        };
+
+        let res = send
+            .send(Bytes::copy_from_slice(buf))
+            .map(|_| buf.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "Broken pipe"));
+
+        Poll::Ready(res)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.send = None;
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use crate::{net::Pipe, Builder, Result};
+
+    #[test]
+    fn loopback() -> Result {
+        // Messages over the network are always dropped
+        let mut sim = Builder::new().fail_rate(1.0).repair_rate(0.0).build();
+
+        sim.client("client", async {
+            let (mut rx, mut tx) = Pipe::new();
+
+            let writer = tokio::spawn(async move {
+                tx.write_all(b"hello").await?;
+                tx.write_all(b" world").await?;
+                tx.shutdown().await
+            });
+
+            let mut buf = String::new();
+            rx.read_to_string(&mut buf).await?;
+            assert_eq!("hello world", buf);
+
+            writer.await??;
+
+            Ok(())
+        });
+
+        sim.run()
+    }
+}
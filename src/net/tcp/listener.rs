@@ -1,4 +1,11 @@
-use std::{io::Result, net::SocketAddr, sync::Arc};
+use std::{
+    future::{self, Future},
+    io::Result,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{ready, Context, Poll},
+};
 
 use tokio::sync::Notify;
 
@@ -14,29 +21,38 @@ use crate::{
 pub struct TcpListener {
     local_addr: SocketAddr,
     notify: Arc<Notify>,
+    /// Waits for SYNs to be delivered. Stored so that `poll_accept` is able to
+    /// register for wakeups across calls.
+    notified: Mutex<Option<Notified>>,
 }
 
+type Notified = Pin<Box<dyn Future<Output = ()> + Send>>;
+
 impl TcpListener {
     pub(crate) fn new(local_addr: SocketAddr, notify: Arc<Notify>) -> Self {
-        Self { local_addr, notify }
+        Self {
+            local_addr,
+            notify,
+            notified: Mutex::new(None),
+        }
     }
 
     /// Creates a new TcpListener, which will be bound to the specified address.
     ///
     /// The returned listener is ready for accepting connections.
     ///
-    /// Only 0.0.0.0 is currently supported.
+    /// Only the unspecified address, or one of the host's own addresses (see
+    /// [`Sim::add_host_interface`](crate::Sim::add_host_interface)), is
+    /// currently supported. Binding to 0.0.0.0 receives messages sent to any of
+    /// the host's addresses. Binding to `::` on a host with an IPv6 address
+    /// receives only IPv6 messages, and takes precedence over 0.0.0.0 for
+    /// those.
     pub async fn bind<A: ToSocketAddrs>(addr: A) -> Result<TcpListener> {
         World::current(|world| {
-            let mut addr = addr.to_socket_addr(&world.dns);
+            let addr = addr.to_socket_addr(&world.dns);
             let host = world.current_host_mut();
-
-            if !addr.ip().is_unspecified() {
-                panic!("{addr} is not supported");
-            }
-
-            // Unspecified -> host's IP
-            addr.set_ip(host.addr);
+            let addr = host.bind_addr(addr);
+            host.check_open_files()?;
 
             host.tcp.bind(addr)
         })
@@ -48,33 +64,53 @@ impl TcpListener {
     /// established, the corresponding [`TcpStream`] and the remote peer’s
     /// address will be returned.
     pub async fn accept(&self) -> Result<(TcpStream, SocketAddr)> {
+        future::poll_fn(|cx| self.poll_accept(cx)).await
+    }
+
+    /// Polls to accept a new incoming connection to this listener.
+    ///
+    /// If there is no connection to accept, `Poll::Pending` is returned and the
+    /// current task will be notified by a waker. Note that on multiple calls to
+    /// `poll_accept`, only the `Waker` from the `Context` passed to the most
+    /// recent call is scheduled to receive a wakeup.
+    pub fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<Result<(TcpStream, SocketAddr)>> {
         loop {
             let maybe_accept = World::current(|world| {
+                let buffer_size = world.tcp_buffer_size;
+                let now = world.topology.elapsed();
                 let host = world.current_host_mut();
-                let (syn, origin) = host.tcp.accept(self.local_addr)?;
+                let (syn, origin, local_addr) = host.tcp.accept(self.local_addr)?;
 
-                tracing::trace!(target: TRACING_TARGET, dst = ?origin, src = ?self.local_addr, protocol = %"TCP SYN", "Recv");
+                tracing::trace!(target: TRACING_TARGET, dst = ?origin, src = ?local_addr, protocol = %"TCP SYN", "Recv");
 
                 // Send SYN-ACK -> origin. If Ok we proceed (acts as the ACK),
                 // else we return early to avoid host mutations.
                 let ack = syn.ack.send(());
-                tracing::trace!(target: TRACING_TARGET, src = ?self.local_addr, dst = ?origin, protocol = %"TCP SYN-ACK", "Send");
+                tracing::trace!(target: TRACING_TARGET, src = ?local_addr, dst = ?origin, protocol = %"TCP SYN-ACK", "Send");
 
                 if ack.is_err() {
                     return None;
                 }
 
-                let pair = SocketPair::new(self.local_addr, origin);
-                let rx = host.tcp.new_stream(pair);
+                let pair = SocketPair::new(local_addr, origin);
+                let rx = host.tcp.new_stream(pair, true, Some(now));
 
-                Some((TcpStream::new(pair, rx), origin))
+                Some((TcpStream::new(pair, rx, buffer_size), origin))
             });
 
+            let mut notified = self.notified.lock().unwrap();
+
             if let Some(accepted) = maybe_accept {
-                return Ok(accepted);
+                *notified = None;
+                return Poll::Ready(Ok(accepted));
             }
 
-            self.notify.notified().await;
+            let notify = self.notify.clone();
+            let fut =
+                notified.get_or_insert_with(|| Box::pin(async move { notify.notified().await }));
+
+            ready!(fut.as_mut().poll(cx));
+            *notified = None;
         }
     }
 
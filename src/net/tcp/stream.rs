@@ -1,31 +1,97 @@
 use std::{
+    collections::VecDeque,
     fmt::Debug,
-    io::{self, Result},
-    net::SocketAddr,
+    future::{self, Future},
+    io::{self, IoSlice, Result},
+    net::{Ipv6Addr, SocketAddr},
     pin::Pin,
-    sync::Arc,
+    sync::{Arc, Mutex},
     task::{ready, Context, Poll},
+    time::Duration,
 };
 
-use bytes::{Buf, Bytes};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use tokio::{
-    io::{AsyncRead, AsyncWrite, ReadBuf},
-    sync::{mpsc, oneshot},
+    io::{AsyncBufRead, AsyncRead, AsyncReadExt, AsyncWrite, Interest, ReadBuf, Ready},
+    sync::{mpsc, mpsc::error::TryRecvError, oneshot},
+    time::Sleep,
 };
+use tokio_util::sync::CancellationToken;
 
 use crate::{
     envelope::{Protocol, Segment, Syn},
     host::SequencedSegment,
     net::SocketPair,
     world::World,
-    ToSocketAddrs, TRACING_TARGET,
+    TimelineEventKind, ToSocketAddrs, TRACING_TARGET,
 };
 
 use super::split_owned::{OwnedReadHalf, OwnedWriteHalf};
 
+/// How long to wait for an IPv6 connection before also trying IPv4, as
+/// recommended by RFC 8305.
+const HAPPY_EYEBALLS_DELAY: Duration = Duration::from_millis(250);
+
+/// Options for [`TcpStream::connect_with_options`].
+#[derive(Debug, Clone, Default)]
+pub struct ConnectOptions {
+    /// Fail with [`io::ErrorKind::TimedOut`] if the connection is not
+    /// established within this duration.
+    pub timeout: Option<Duration>,
+
+    /// The local address to connect from. A port of 0 assigns an ephemeral
+    /// port. Defaults to the host's address and an ephemeral port.
+    pub bind_addr: Option<SocketAddr>,
+
+    /// Accepted for compatibility. The simulated handshake carries no data, so
+    /// this has no effect.
+    pub tcp_fastopen: bool,
+}
+
+/// The ready states of a [`TcpStream`], returned by [`TcpStream::ready`].
+///
+/// The guard borrows the stream, so non-blocking I/O can be performed
+/// through it for as long as it is held.
+#[derive(Debug)]
+pub struct ReadyGuard<'a> {
+    stream: &'a TcpStream,
+    ready: Ready,
+}
+
+impl ReadyGuard<'_> {
+    /// The ready states that were satisfied.
+    pub fn ready(&self) -> Ready {
+        self.ready
+    }
+
+    /// Whether the stream was ready for reading.
+    pub fn is_readable(&self) -> bool {
+        self.ready.is_readable()
+    }
+
+    /// Whether the stream was ready for writing.
+    pub fn is_writable(&self) -> bool {
+        self.ready.is_writable()
+    }
+
+    /// Same as [`TcpStream::try_read`].
+    pub fn try_read(&self, buf: &mut [u8]) -> Result<usize> {
+        self.stream.try_read(buf)
+    }
+
+    /// Same as [`TcpStream::try_write`].
+    pub fn try_write(&self, buf: &[u8]) -> Result<usize> {
+        self.stream.try_write(buf)
+    }
+}
+
 /// A simulated TCP stream between a local and a remote socket.
 ///
 /// All methods must be called from a host within a Turmoil simulation.
+///
+/// Writes complete as soon as the data fits in the send buffer, while
+/// [`flush`](tokio::io::AsyncWriteExt::flush) waits until all written data has
+/// been delivered to the peer.
 #[derive(Debug)]
 pub struct TcpStream {
     read_half: ReadHalf,
@@ -33,20 +99,33 @@ pub struct TcpStream {
 }
 
 impl TcpStream {
-    pub(crate) fn new(pair: SocketPair, receiver: mpsc::Receiver<SequencedSegment>) -> Self {
+    pub(crate) fn new(
+        pair: SocketPair,
+        receiver: mpsc::Receiver<SequencedSegment>,
+        buffer_size: Option<usize>,
+    ) -> Self {
         let pair = Arc::new(pair);
         let read_half = ReadHalf {
             pair: pair.clone(),
-            rx: Rx {
+            rx: Mutex::new(Rx {
                 recv: receiver,
                 buffer: None,
-            },
-            is_closed: false,
+                pending: VecDeque::new(),
+                is_closed: false,
+                timeout: None,
+                deadline: None,
+            }),
         };
 
         let write_half = WriteHalf {
             pair,
             is_shutdown: false,
+            tx: Mutex::new(Tx {
+                buffer_size,
+                timeout: None,
+                deadline: None,
+                polled_at: 0,
+            }),
         };
 
         Self {
@@ -56,7 +135,115 @@ impl TcpStream {
     }
 
     /// Opens a TCP connection to a remote host.
+    ///
+    /// When connecting by hostname and both this host and the remote host
+    /// have an IPv6 and an IPv4 address, see
+    /// [`Sim::add_host_interface`](crate::Sim::add_host_interface), the two
+    /// are raced as in Happy Eyeballs (RFC 6555). IPv6 is tried first and IPv4
+    /// 250ms of simulated time later. The first connection established wins
+    /// and the other attempt is abandoned.
     pub async fn connect<A: ToSocketAddrs>(addr: A) -> Result<TcpStream> {
+        TcpStream::connect_with_options(addr, ConnectOptions::default()).await
+    }
+
+    /// Opens a TCP connection to a remote host over IPv6.
+    ///
+    /// The connection is made from one of the host's IPv6 addresses, see
+    /// [`Sim::add_host_interface`](crate::Sim::add_host_interface), failing
+    /// with [`io::ErrorKind::NetworkUnreachable`] if it has none.
+    pub async fn connect_v6(addr: (Ipv6Addr, u16)) -> Result<TcpStream> {
+        TcpStream::connect(SocketAddr::from(addr)).await
+    }
+
+    /// Opens a TCP connection to a remote host whose address is only known
+    /// once `addr` resolves, e.g. after an asynchronous lookup.
+    ///
+    /// The future is awaited on the calling host before connecting, so any
+    /// simulated work it does happens in that host's context.
+    pub async fn connect_lazy<A: ToSocketAddrs>(
+        addr: impl Future<Output = A>,
+    ) -> Result<TcpStream> {
+        TcpStream::connect(addr.await).await
+    }
+
+    /// Opens a TCP connection to a remote host, configured by `opts`.
+    pub async fn connect_with_options<A: ToSocketAddrs>(
+        addr: A,
+        opts: ConnectOptions,
+    ) -> Result<TcpStream> {
+        if opts.bind_addr.is_none() && addr.is_hostname() {
+            let addrs =
+                World::current(|world| world.happy_eyeballs_addrs(addr.to_socket_addr(&world.dns)));
+
+            if let Some((preferred, fallback)) = addrs {
+                return TcpStream::connect_happy_eyeballs(preferred, fallback, opts).await;
+            }
+        }
+
+        TcpStream::connect_priv(addr, opts, None).await
+    }
+
+    /// Race connections to `preferred` and `fallback`, starting the latter
+    /// after [`HAPPY_EYEBALLS_DELAY`] or as soon as the former fails.
+    async fn connect_happy_eyeballs(
+        preferred: SocketAddr,
+        fallback: SocketAddr,
+        opts: ConnectOptions,
+    ) -> Result<TcpStream> {
+        let preferred_cancel = CancellationToken::new();
+        let first =
+            TcpStream::connect_priv(preferred, opts.clone(), Some(preferred_cancel.clone()));
+        tokio::pin!(first);
+
+        tokio::select! {
+            biased;
+            res = &mut first => match res {
+                Ok(s) => return Ok(s),
+                Err(_) => return TcpStream::connect_priv(fallback, opts, None).await,
+            },
+            _ = tokio::time::sleep(HAPPY_EYEBALLS_DELAY) => {}
+        }
+
+        tracing::trace!(target: TRACING_TARGET, ?preferred, ?fallback, protocol = %"TCP", "Happy Eyeballs");
+
+        let fallback_cancel = CancellationToken::new();
+        let second = TcpStream::connect_priv(fallback, opts, Some(fallback_cancel.clone()));
+        tokio::pin!(second);
+
+        let (res, cancel, loser) = tokio::select! {
+            biased;
+            res = &mut first => (res, fallback_cancel, second),
+            res = &mut second => (res, preferred_cancel, first),
+        };
+
+        match res {
+            Ok(s) => {
+                // Abandon the other attempt, closing it should it have
+                // connected in the meantime.
+                cancel.cancel();
+                let _ = loser.await;
+                Ok(s)
+            }
+            Err(_) => loser.await,
+        }
+    }
+
+    /// Opens a TCP connection to a remote host, aborting the attempt if
+    /// `cancel_token` is cancelled first.
+    ///
+    /// A cancelled attempt fails with [`io::ErrorKind::Interrupted`].
+    pub async fn connect_cancellable<A: ToSocketAddrs>(
+        addr: A,
+        cancel_token: CancellationToken,
+    ) -> Result<TcpStream> {
+        TcpStream::connect_priv(addr, ConnectOptions::default(), Some(cancel_token)).await
+    }
+
+    async fn connect_priv<A: ToSocketAddrs>(
+        addr: A,
+        opts: ConnectOptions,
+        cancel_token: Option<CancellationToken>,
+    ) -> Result<TcpStream> {
         let (ack, syn_ack) = oneshot::channel();
 
         let (pair, rx) = World::current(|world| {
@@ -64,22 +251,101 @@ impl TcpStream {
             let syn = Segment::Syn(Syn { ack });
 
             let host = world.current_host_mut();
-            let local_addr = (host.addr, host.assign_ephemeral_port()).into();
+
+            if host.tcp.is_at_connection_limit() {
+                return Err(io::Error::new(
+                    io::ErrorKind::ConnectionRefused,
+                    "connection limit reached",
+                ));
+            }
+
+            host.check_open_files()?;
+
+            let local_addr = match opts.bind_addr {
+                Some(addr) => {
+                    let mut addr = host.bind_addr(addr);
+
+                    if addr.port() == 0 {
+                        addr.set_port(host.assign_ephemeral_port());
+                    } else if host.tcp.is_port_assigned(addr.port()) {
+                        return Err(io::Error::new(io::ErrorKind::AddrInUse, addr.to_string()));
+                    }
+
+                    addr
+                }
+                None => {
+                    let Some(ip) = host.source_addr(dst.ip()) else {
+                        return Err(io::Error::new(
+                            io::ErrorKind::NetworkUnreachable,
+                            dst.to_string(),
+                        ));
+                    };
+
+                    (ip, host.assign_ephemeral_port()).into()
+                }
+            };
 
             let pair = SocketPair::new(local_addr, dst);
-            let rx = host.tcp.new_stream(pair);
-            world.send_message(local_addr, dst, Protocol::Tcp(syn));
+            let rx = host.tcp.new_stream(pair, false, None);
 
-            (pair, rx)
-        });
+            if let Err(e) = world.send_message(local_addr, dst, Protocol::Tcp(syn)) {
+                world.current_host_mut().tcp.remove_stream(pair);
+                return Err(e);
+            }
 
-        syn_ack.await.map_err(|_| {
-            io::Error::new(io::ErrorKind::ConnectionRefused, pair.remote.to_string())
+            Ok((pair, rx))
         })?;
 
+        let refused = |_| io::Error::new(io::ErrorKind::ConnectionRefused, pair.remote.to_string());
+
+        let connected = async {
+            match opts.timeout {
+                Some(timeout) => match tokio::time::timeout(timeout, syn_ack).await {
+                    Ok(res) => res.map_err(refused),
+                    Err(_) => Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        pair.remote.to_string(),
+                    )),
+                },
+                None => syn_ack.await.map_err(refused),
+            }
+        };
+
+        let cancelled = async {
+            match &cancel_token {
+                Some(token) => token.cancelled().await,
+                None => future::pending().await,
+            }
+        };
+
+        let res = tokio::select! {
+            res = connected => res,
+            _ = cancelled => Err(io::Error::new(io::ErrorKind::Interrupted, "connect cancelled")),
+        };
+
+        if let Err(e) = res {
+            World::current(|world| world.current_host_mut().tcp.remove_stream(pair));
+            return Err(e);
+        }
+
         tracing::trace!(target: TRACING_TARGET, dst = ?pair.local, src = ?pair.remote, protocol = %"TCP SYN-ACK", "Recv");
 
-        Ok(TcpStream::new(pair, rx))
+        let buffer_size = World::current(|world| {
+            world
+                .topology
+                .log
+                .event(TimelineEventKind::ConnectionOpened {
+                    local: pair.local,
+                    remote: pair.remote,
+                });
+
+            let now = world.topology.elapsed();
+            world.current_host_mut().tcp.establish_stream(pair, now);
+
+            world.tcp_buffer_size
+        });
+
+        Ok(TcpStream::new(pair, rx, buffer_size))
     }
 
     /// Returns the local address that this stream is bound to.
@@ -92,6 +358,310 @@ impl TcpStream {
         Ok(self.read_half.pair.remote)
     }
 
+    /// Sets the receive timeout (`SO_RCVTIMEO`) of the stream.
+    ///
+    /// Once set, a read that doesn't receive data within `timeout` of
+    /// simulated time fails with [`io::ErrorKind::TimedOut`]. The timeout
+    /// restarts with each read. `None` disables the timeout, and a zero
+    /// duration is rejected with [`io::ErrorKind::InvalidInput`].
+    pub fn set_recv_timeout(&self, timeout: Option<Duration>) -> Result<()> {
+        if timeout == Some(Duration::ZERO) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot set a 0 duration timeout",
+            ));
+        }
+
+        let mut rx = self.read_half.rx.lock().unwrap();
+        rx.timeout = timeout;
+        rx.deadline = None;
+
+        Ok(())
+    }
+
+    /// Returns the receive timeout of the stream, see
+    /// [`TcpStream::set_recv_timeout`].
+    pub fn recv_timeout(&self) -> Result<Option<Duration>> {
+        Ok(self.read_half.rx.lock().unwrap().timeout)
+    }
+
+    /// Returns the number of bytes that can be read without waiting, without
+    /// consuming them.
+    ///
+    /// This counts the bytes received up to the end of the stream, if the peer
+    /// has shut down writes.
+    pub fn bytes_pending_read(&self) -> usize {
+        self.read_half.rx.lock().unwrap().bytes_pending()
+    }
+
+    /// Returns the number of bytes written to the stream that are still in
+    /// the simulated network, i.e. not yet delivered to the peer.
+    ///
+    /// Must be called from within a Turmoil simulation.
+    pub fn bytes_pending_write(&self) -> usize {
+        let pair = &self.write_half.pair;
+
+        World::current(|world| world.topology.tcp_bytes_in_flight(pair.local, pair.remote))
+    }
+
+    /// Sets the send timeout (`SO_SNDTIMEO`) of the stream.
+    ///
+    /// A write that blocks for longer than `timeout` of simulated time fails
+    /// with [`io::ErrorKind::TimedOut`]. Writes only block once the send
+    /// buffer is full, see [`TcpStream::set_send_buffer_size`]. `None`
+    /// disables the timeout, and a zero duration is rejected with
+    /// [`io::ErrorKind::InvalidInput`].
+    pub fn set_send_timeout(&self, timeout: Option<Duration>) -> Result<()> {
+        if timeout == Some(Duration::ZERO) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot set a 0 duration timeout",
+            ));
+        }
+
+        let mut tx = self.write_half.tx.lock().unwrap();
+        tx.timeout = timeout;
+        tx.deadline = None;
+
+        Ok(())
+    }
+
+    /// Returns the send timeout of the stream, see
+    /// [`TcpStream::set_send_timeout`].
+    pub fn send_timeout(&self) -> Result<Option<Duration>> {
+        Ok(self.write_half.tx.lock().unwrap().timeout)
+    }
+
+    /// Sets the send buffer size (`SO_SNDBUF`) of the stream.
+    ///
+    /// At most `size` bytes written to the stream may be in the simulated
+    /// network at once, i.e. not yet delivered to the peer. Writes block while
+    /// the buffer is full. The default is set with
+    /// [`Sim::set_default_tcp_buffer_size`](crate::Sim::set_default_tcp_buffer_size),
+    /// unbounded if not set, and a size of zero is rejected with
+    /// [`io::ErrorKind::InvalidInput`].
+    pub fn set_send_buffer_size(&self, size: usize) -> Result<()> {
+        if size == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot set a 0 byte send buffer",
+            ));
+        }
+
+        self.write_half.tx.lock().unwrap().buffer_size = Some(size);
+
+        Ok(())
+    }
+
+    /// Returns the send buffer size of the stream, or `None` if it is
+    /// unbounded, see [`TcpStream::set_send_buffer_size`].
+    pub fn send_buffer_size(&self) -> Result<Option<usize>> {
+        Ok(self.write_half.tx.lock().unwrap().buffer_size)
+    }
+
+    /// Tries to read data from the stream into the provided buffer, returning
+    /// how many bytes were read.
+    ///
+    /// Receives any pending data from the socket but does not wait for new
+    /// data to arrive. On success, returns the number of bytes read. Because
+    /// `try_read()` is non-blocking, the buffer does not have to be stored by
+    /// the async task and can exist entirely on the stack.
+    ///
+    /// # Return
+    ///
+    /// If data is successfully read, `Ok(n)` is returned, where `n` is the
+    /// number of bytes read. If `n` is `0`, then it can indicate one of two
+    /// scenarios:
+    ///
+    /// 1. The stream's read half is closed and will no longer yield data.
+    /// 2. The specified buffer was 0 bytes in length.
+    ///
+    /// If the stream is not ready to read data,
+    /// `Err(io::ErrorKind::WouldBlock)` is returned.
+    pub fn try_read(&self, buf: &mut [u8]) -> Result<usize> {
+        self.read_half.try_read_priv(buf)
+    }
+
+    /// Try to write a buffer to the stream, returning how many bytes were
+    /// written.
+    ///
+    /// The function will attempt to write the entire contents of `buf`, but
+    /// only part of the buffer may be written.
+    ///
+    /// # Return
+    ///
+    /// If data is successfully written, `Ok(n)` is returned, where `n` is the
+    /// number of bytes written. If the stream is not ready to write data,
+    /// `Err(io::ErrorKind::WouldBlock)` is returned. This only happens once the
+    /// send buffer is full, see [`TcpStream::set_send_buffer_size`].
+    pub fn try_write(&self, buf: &[u8]) -> Result<usize> {
+        self.write_half.try_write_priv(buf)
+    }
+
+    /// Same as [`try_write`](Self::try_write), but writes from several
+    /// buffers, which are sent as a single segment.
+    pub fn try_write_vectored(&self, bufs: &[IoSlice<'_>]) -> Result<usize> {
+        self.write_half.try_write_vectored_priv(bufs)
+    }
+
+    /// Pulls some bytes from the stream, appending them to `buf`. On success,
+    /// returns the number of bytes read, which is `0` if the read half is
+    /// closed or `buf` has no remaining capacity.
+    ///
+    /// This is the equivalent of
+    /// [`read_buf`](fn@tokio::io::AsyncReadExt::read_buf), but bytes are
+    /// copied straight from the received segment into `buf`, without first
+    /// initializing its spare capacity.
+    pub async fn read_buf<B: BufMut>(&mut self, buf: &mut B) -> Result<usize> {
+        future::poll_fn(|cx| self.read_half.poll_read_buf_priv(cx, buf)).await
+    }
+
+    /// Writes all remaining bytes of `buf` to the stream, advancing it to the
+    /// end.
+    ///
+    /// This is the equivalent of
+    /// [`write_all_buf`](fn@tokio::io::AsyncWriteExt::write_all_buf). The
+    /// bytes are sent as a single segment, or one per write if the send buffer
+    /// is smaller, and, for a `Bytes` buffer, without being copied.
+    pub async fn write_all_buf<B: Buf>(&mut self, buf: &mut B) -> Result<()> {
+        while buf.has_remaining() {
+            let capacity = future::poll_fn(|cx| self.write_half.poll_send_capacity(cx)).await?;
+
+            let bytes = buf.copy_to_bytes(buf.remaining().min(capacity));
+            self.write_half.write_bytes(bytes)?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads the exact number of bytes required to fill `buf`, failing with
+    /// [`io::ErrorKind::TimedOut`] if it isn't filled within `timeout` of
+    /// simulated time.
+    ///
+    /// Errors are otherwise the same as
+    /// [`read_exact`](fn@tokio::io::AsyncReadExt::read_exact). On timeout, any
+    /// bytes read into `buf` so far are consumed from the stream.
+    pub async fn read_exact_timeout(&mut self, buf: &mut [u8], timeout: Duration) -> Result<usize> {
+        match tokio::time::timeout(timeout, self.read_exact(buf)).await {
+            Ok(res) => res,
+            Err(_) => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("read not completed within {timeout:?}"),
+            )),
+        }
+    }
+
+    /// Waits for the stream to become readable.
+    ///
+    /// This function is usually paired with [`try_read`](Self::try_read). The
+    /// stream is readable once data is available, or the connection has been
+    /// closed or reset, in which case the subsequent read returns EOF or the
+    /// error.
+    ///
+    /// # Cancel safety
+    ///
+    /// This method is cancel safe. Once a readiness event occurs, the method
+    /// will continue to return immediately until the data is read.
+    pub async fn readable(&self) -> Result<()> {
+        future::poll_fn(|cx| self.poll_read_ready(cx)).await
+    }
+
+    /// Waits for any of the requested ready states, returning a guard that
+    /// reports which are satisfied.
+    ///
+    /// The stream is readable as described in [`readable`](Self::readable)
+    /// and writable as described in [`writable`](Self::writable). This returns
+    /// as soon as any state in `interest` is satisfied.
+    ///
+    /// # Cancel safety
+    ///
+    /// This method is cancel safe.
+    pub async fn ready(&self, interest: Interest) -> Result<ReadyGuard<'_>> {
+        let ready = future::poll_fn(|cx| {
+            let mut ready = Ready::EMPTY;
+
+            if interest.is_readable() {
+                match self.poll_read_ready(cx) {
+                    Poll::Ready(Ok(())) => ready |= Ready::READABLE,
+                    Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                    Poll::Pending => {}
+                }
+            }
+
+            if interest.is_writable() {
+                match self.poll_write_ready(cx) {
+                    Poll::Ready(Ok(())) => ready |= Ready::WRITABLE,
+                    Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                    Poll::Pending => {}
+                }
+            }
+
+            if ready.is_empty() {
+                Poll::Pending
+            } else {
+                Poll::Ready(Ok(ready))
+            }
+        })
+        .await?;
+
+        Ok(ReadyGuard {
+            stream: self,
+            ready,
+        })
+    }
+
+    /// Polls for read readiness.
+    ///
+    /// If the stream is not currently ready for reading, this method will
+    /// store a clone of the `Waker` from the provided `Context`. When the
+    /// stream becomes ready for reading, `Waker::wake` will be called on the
+    /// waker.
+    ///
+    /// Note that on multiple calls to `poll_read_ready` or `poll_read`, only
+    /// the `Waker` from the `Context` passed to the most recent call is
+    /// scheduled to receive a wakeup.
+    ///
+    /// The stream is also ready once the connection has been closed or reset,
+    /// in which case the subsequent read returns EOF or the error.
+    pub fn poll_read_ready(&self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.read_half.poll_read_ready_priv(cx)
+    }
+
+    /// Waits for the stream to become writable.
+    ///
+    /// This function is usually paired with [`try_write`](Self::try_write).
+    /// The stream is writable unless its send buffer is full, see
+    /// [`set_send_buffer_size`](Self::set_send_buffer_size).
+    ///
+    /// # Cancel safety
+    ///
+    /// This method is cancel safe.
+    pub async fn writable(&self) -> Result<()> {
+        future::poll_fn(|cx| self.poll_write_ready(cx)).await
+    }
+
+    /// Polls for write readiness.
+    ///
+    /// The stream is ready for writing unless its send buffer is full, in
+    /// which case the waker is notified as data in flight is delivered.
+    pub fn poll_write_ready(&self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.write_half.poll_write_ready_priv(cx)
+    }
+
+    /// Returns the value of the `SO_ERROR` option.
+    ///
+    /// The simulated stream reports an error once the connection has been
+    /// reset, e.g. due to the peer sending a RST. The error is cleared by this
+    /// call.
+    pub fn take_error(&self) -> Result<Option<io::Error>> {
+        Ok(World::current(|world| {
+            world
+                .current_host_mut()
+                .tcp
+                .take_error(*self.read_half.pair)
+        }))
+    }
+
     pub(crate) fn reunite(read_half: ReadHalf, write_half: WriteHalf) -> Self {
         Self {
             read_half,
@@ -120,9 +690,8 @@ impl TcpStream {
 
 pub(crate) struct ReadHalf {
     pub(crate) pair: Arc<SocketPair>,
-    rx: Rx,
-    /// FIN received, EOF for reads
-    is_closed: bool,
+    /// Behind a mutex to support reads via `&self`, such as `try_read`.
+    rx: Mutex<Rx>,
 }
 
 struct Rx {
@@ -132,39 +701,190 @@ struct Rx {
     /// This is used to support read impls by stashing available bytes for
     /// subsequent reads.
     buffer: Option<Bytes>,
+    /// Segments taken off the channel by [`TcpStream::bytes_pending_read`],
+    /// which are received before any others.
+    pending: VecDeque<SequencedSegment>,
+    /// FIN received, EOF for reads
+    is_closed: bool,
+    /// How long a read may wait for data, see
+    /// [`TcpStream::set_recv_timeout`].
+    timeout: Option<Duration>,
+    /// When the pending read times out, reset once it completes.
+    deadline: Option<Pin<Box<Sleep>>>,
+}
+
+impl Rx {
+    /// Poll for the next segment, failing with `TimedOut` once the receive
+    /// timeout elapses without one arriving.
+    fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Result<Option<SequencedSegment>>> {
+        if let Poll::Ready(seg) = self.poll_next(cx) {
+            self.deadline = None;
+            return Poll::Ready(Ok(seg));
+        }
+
+        let Some(timeout) = self.timeout else {
+            return Poll::Pending;
+        };
+
+        let deadline = self
+            .deadline
+            .get_or_insert_with(|| Box::pin(tokio::time::sleep(timeout)));
+
+        ready!(deadline.as_mut().poll(cx));
+        self.deadline = None;
+
+        Poll::Ready(Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "read timed out",
+        )))
+    }
+
+    fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<SequencedSegment>> {
+        match self.pending.pop_front() {
+            Some(seg) => Poll::Ready(Some(seg)),
+            None => self.recv.poll_recv(cx),
+        }
+    }
+
+    fn try_next(&mut self) -> std::result::Result<SequencedSegment, TryRecvError> {
+        match self.pending.pop_front() {
+            Some(seg) => Ok(seg),
+            None => self.recv.try_recv(),
+        }
+    }
+
+    /// The number of bytes that can be read before reaching the end of the
+    /// received data.
+    fn bytes_pending(&mut self) -> usize {
+        if self.is_closed {
+            return 0;
+        }
+
+        while let Ok(seg) = self.recv.try_recv() {
+            self.pending.push_back(seg);
+        }
+
+        let buffered = self.buffer.as_ref().map_or(0, Bytes::len);
+        let pending: usize = self
+            .pending
+            .iter()
+            .map_while(|seg| match seg {
+                SequencedSegment::Data(bytes) => Some(bytes.len()),
+                SequencedSegment::Fin => None,
+            })
+            .sum();
+
+        buffered + pending
+    }
 }
 
 impl ReadHalf {
     fn poll_read_priv(&mut self, cx: &mut Context<'_>, buf: &mut ReadBuf) -> Poll<Result<()>> {
-        if self.is_closed || buf.capacity() == 0 {
+        let rx = self.rx.get_mut().unwrap();
+
+        if rx.is_closed || buf.capacity() == 0 {
             return Poll::Ready(Ok(()));
         }
 
-        if let Some(bytes) = self.rx.buffer.take() {
-            self.rx.buffer = Self::put_slice(bytes, buf);
+        if let Some(bytes) = rx.buffer.take() {
+            rx.buffer = Self::put_slice(bytes, buf);
+            rx.deadline = None;
 
             return Poll::Ready(Ok(()));
         }
 
-        match ready!(self.rx.recv.poll_recv(cx)) {
+        match ready!(rx.poll_recv(cx))? {
             Some(seg) => {
-                tracing::trace!(target: TRACING_TARGET, dst = ?self.pair.local, src = ?self.pair.remote, protocol = %seg, "Recv");
+                rx.recv_segment(&self.pair, seg, buf);
+                Poll::Ready(Ok(()))
+            }
+            None => Poll::Ready(Err(connection_reset())),
+        }
+    }
 
-                match seg {
-                    SequencedSegment::Data(bytes) => {
-                        self.rx.buffer = Self::put_slice(bytes, buf);
-                    }
-                    SequencedSegment::Fin => {
-                        self.is_closed = true;
-                    }
-                }
+    fn poll_read_buf_priv(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &mut impl BufMut,
+    ) -> Poll<Result<usize>> {
+        if !buf.has_remaining_mut() {
+            return Poll::Ready(Ok(0));
+        }
 
-                Poll::Ready(Ok(()))
+        let bytes = ready!(self.poll_fill_buf_priv(cx))?;
+        let n = bytes.len().min(buf.remaining_mut());
+        buf.put_slice(&bytes[..n]);
+
+        self.consume_priv(n);
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_fill_buf_priv(&mut self, cx: &mut Context<'_>) -> Poll<Result<&[u8]>> {
+        let rx = self.rx.get_mut().unwrap();
+
+        if rx.buffer.is_none() && !rx.is_closed {
+            match ready!(rx.poll_recv(cx))? {
+                Some(seg) => rx.recv_segment(&self.pair, seg, &mut ReadBuf::new(&mut [])),
+                None => return Poll::Ready(Err(connection_reset())),
             }
-            None => Poll::Ready(Err(io::Error::new(
-                io::ErrorKind::ConnectionReset,
-                "Connection reset",
-            ))),
+        }
+
+        Poll::Ready(Ok(rx.buffer.as_deref().unwrap_or_default()))
+    }
+
+    fn consume_priv(&mut self, amt: usize) {
+        let rx = self.rx.get_mut().unwrap();
+
+        if let Some(bytes) = rx.buffer.as_mut() {
+            bytes.advance(amt);
+
+            if bytes.is_empty() {
+                rx.buffer = None;
+            }
+        }
+    }
+
+    fn poll_read_ready_priv(&self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let mut rx = self.rx.lock().unwrap();
+
+        if rx.is_closed || rx.buffer.is_some() {
+            return Poll::Ready(Ok(()));
+        }
+
+        // Stash the received segment for the next read. A reset is surfaced by
+        // that read as well.
+        if let Some(seg) = ready!(rx.poll_next(cx)) {
+            rx.recv_segment(&self.pair, seg, &mut ReadBuf::new(&mut []));
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    fn try_read_priv(&self, buf: &mut [u8]) -> Result<usize> {
+        let mut rx = self.rx.lock().unwrap();
+
+        if rx.is_closed || buf.is_empty() {
+            return Ok(0);
+        }
+
+        let mut buf = ReadBuf::new(buf);
+
+        if let Some(bytes) = rx.buffer.take() {
+            rx.buffer = Self::put_slice(bytes, &mut buf);
+
+            return Ok(buf.filled().len());
+        }
+
+        match rx.try_next() {
+            Ok(seg) => {
+                rx.recv_segment(&self.pair, seg, &mut buf);
+                Ok(buf.filled().len())
+            }
+            Err(TryRecvError::Empty) => Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                "socket receive queue is empty",
+            )),
+            Err(TryRecvError::Disconnected) => Err(connection_reset()),
         }
     }
 
@@ -187,11 +907,32 @@ impl ReadHalf {
     }
 }
 
+impl Rx {
+    fn recv_segment(&mut self, pair: &SocketPair, seg: SequencedSegment, buf: &mut ReadBuf) {
+        tracing::trace!(target: TRACING_TARGET, dst = ?pair.local, src = ?pair.remote, protocol = %seg, "Recv");
+
+        match seg {
+            SequencedSegment::Data(bytes) => {
+                self.buffer = ReadHalf::put_slice(bytes, buf);
+            }
+            SequencedSegment::Fin => {
+                self.is_closed = true;
+            }
+        }
+    }
+}
+
+fn connection_reset() -> io::Error {
+    io::Error::new(io::ErrorKind::ConnectionReset, "Connection reset")
+}
+
 impl Debug for ReadHalf {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let is_closed = self.rx.lock().map(|rx| rx.is_closed).ok();
+
         f.debug_struct("ReadHalf")
             .field("pair", &self.pair)
-            .field("is_closed", &self.is_closed)
+            .field("is_closed", &is_closed)
             .finish()
     }
 }
@@ -200,32 +941,199 @@ pub(crate) struct WriteHalf {
     pub(crate) pair: Arc<SocketPair>,
     /// FIN sent, closed for writes
     is_shutdown: bool,
+    /// Behind a mutex to support configuration via `&self`, such as
+    /// `set_send_timeout`.
+    tx: Mutex<Tx>,
+}
+
+struct Tx {
+    /// Max bytes in flight to the peer, unbounded if not set, see
+    /// [`TcpStream::set_send_buffer_size`].
+    buffer_size: Option<usize>,
+    /// How long a write may block, see [`TcpStream::set_send_timeout`].
+    timeout: Option<Duration>,
+    /// When the blocked write times out, reset once it completes.
+    deadline: Option<Pin<Box<Sleep>>>,
+    /// The network tick at which a blocked write was last polled.
+    polled_at: u64,
+}
+
+impl Tx {
+    /// Poll the send timeout of a blocked write at network tick `tick`,
+    /// failing with `TimedOut` once it elapses.
+    fn poll_timeout(&mut self, cx: &mut Context<'_>, tick: u64) -> Poll<Result<usize>> {
+        // A blocked write is woken and polled on every tick, so if the
+        // deadline wasn't polled on the previous one, the write it belongs
+        // to was cancelled and this is a new write.
+        if self.polled_at + 1 < tick {
+            self.deadline = None;
+        }
+        self.polled_at = tick;
+
+        let Some(timeout) = self.timeout else {
+            return Poll::Pending;
+        };
+
+        let deadline = self
+            .deadline
+            .get_or_insert_with(|| Box::pin(tokio::time::sleep(timeout)));
+
+        ready!(deadline.as_mut().poll(cx));
+        self.deadline = None;
+
+        Poll::Ready(Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "write timed out",
+        )))
+    }
 }
 
 impl WriteHalf {
-    fn poll_write_priv(&self, _cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
+    fn poll_write_priv(&self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
         if buf.remaining() == 0 {
             return Poll::Ready(Ok(0));
         }
 
+        let capacity = ready!(self.poll_send_capacity(cx))?;
+        let len = buf.len().min(capacity);
+
+        Poll::Ready(self.write_bytes(Bytes::copy_from_slice(&buf[..len])))
+    }
+
+    fn try_write_priv(&self, buf: &[u8]) -> Result<usize> {
+        if buf.remaining() == 0 {
+            return Ok(0);
+        }
+
+        let len = buf.len().min(self.try_send_capacity()?);
+
+        self.write_bytes(Bytes::copy_from_slice(&buf[..len]))
+    }
+
+    fn poll_write_vectored_priv(
+        &self,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<Result<usize>> {
+        if bufs.iter().all(|b| b.is_empty()) {
+            return Poll::Ready(Ok(0));
+        }
+
+        let capacity = ready!(self.poll_send_capacity(cx))?;
+
+        Poll::Ready(self.write_vectored(bufs, capacity))
+    }
+
+    fn try_write_vectored_priv(&self, bufs: &[IoSlice<'_>]) -> Result<usize> {
+        if bufs.iter().all(|b| b.is_empty()) {
+            return Ok(0);
+        }
+
+        self.write_vectored(bufs, self.try_send_capacity()?)
+    }
+
+    // The slices are concatenated, up to `capacity` bytes, and sent as a
+    // single segment.
+    fn write_vectored(&self, bufs: &[IoSlice<'_>], capacity: usize) -> Result<usize> {
+        let len = capacity.min(bufs.iter().map(|b| b.len()).sum());
+
+        let mut bytes = BytesMut::with_capacity(len);
+        for buf in bufs {
+            let remaining = len - bytes.len();
+            bytes.extend_from_slice(&buf[..buf.len().min(remaining)]);
+        }
+
+        self.write_bytes(bytes.freeze())
+    }
+
+    /// The number of bytes that may be written before the send buffer is
+    /// full.
+    fn send_capacity(&self, world: &World) -> usize {
+        let buffer_size = self.tx.lock().unwrap().buffer_size;
+
+        buffer_size.map_or(usize::MAX, |size| {
+            let in_flight = world
+                .topology
+                .tcp_bytes_in_flight(self.pair.local, self.pair.remote);
+
+            size.saturating_sub(in_flight)
+        })
+    }
+
+    fn try_send_capacity(&self) -> Result<usize> {
+        self.check_shutdown()?;
+
+        match World::current(|world| self.send_capacity(world)) {
+            0 => Err(io::ErrorKind::WouldBlock.into()),
+            capacity => Ok(capacity),
+        }
+    }
+
+    /// Poll for space in the send buffer, failing with `TimedOut` once the
+    /// send timeout elapses without any.
+    fn poll_send_capacity(&self, cx: &mut Context<'_>) -> Poll<Result<usize>> {
+        if self.poll_write_ready_priv(cx).is_ready() {
+            self.tx.lock().unwrap().deadline = None;
+            return Poll::Ready(self.try_send_capacity());
+        }
+
+        let tick = World::current(|world| world.topology.tick());
+        self.tx.lock().unwrap().poll_timeout(cx, tick)
+    }
+
+    // Ready once there is space in the send buffer, or the write half is
+    // shut down so that writes fail immediately.
+    fn poll_write_ready_priv(&self, cx: &mut Context<'_>) -> Poll<Result<()>> {
         if self.is_shutdown {
-            return Poll::Ready(Err(io::Error::new(
-                io::ErrorKind::BrokenPipe,
-                "Broken pipe",
-            )));
+            return Poll::Ready(Ok(()));
         }
 
-        let res = World::current(|world| {
-            let bytes = Bytes::copy_from_slice(buf);
+        World::current(|world| {
+            if self.send_capacity(world) > 0 {
+                return Poll::Ready(Ok(()));
+            }
+
+            world.topology.wake_on_tick(cx.waker());
+            Poll::Pending
+        })
+    }
+
+    fn check_shutdown(&self) -> Result<()> {
+        if self.is_shutdown {
+            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "Broken pipe"));
+        }
+
+        Ok(())
+    }
+
+    fn write_bytes(&self, bytes: Bytes) -> Result<usize> {
+        self.check_shutdown()?;
+
+        World::current(|world| {
             let len = bytes.len();
 
             let seq = self.seq(world)?;
-            self.send(world, Segment::Data(seq, bytes));
+            self.send(world, Segment::Data(seq, bytes))?;
+            world.current_host_mut().tcp.record_sent(*self.pair, len);
 
             Ok(len)
-        });
+        })
+    }
 
-        Poll::Ready(res)
+    // Completes once all data written has been delivered to the peer.
+    fn poll_flush_priv(&self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        World::current(|world| {
+            let pending = world
+                .topology
+                .tcp_bytes_in_flight(self.pair.local, self.pair.remote);
+
+            if pending == 0 {
+                return Poll::Ready(Ok(()));
+            }
+
+            world.topology.wake_on_tick(cx.waker());
+            Poll::Pending
+        })
     }
 
     fn poll_shutdown_priv(&mut self) -> Poll<Result<()>> {
@@ -238,7 +1146,7 @@ impl WriteHalf {
 
         let res = World::current(|world| {
             let seq = self.seq(world)?;
-            self.send(world, Segment::Fin(seq));
+            self.send(world, Segment::Fin(seq))?;
 
             self.is_shutdown = true;
 
@@ -258,8 +1166,8 @@ impl WriteHalf {
             .ok_or_else(|| io::Error::new(io::ErrorKind::BrokenPipe, "Broken pipe"))
     }
 
-    fn send(&self, world: &mut World, segment: Segment) {
-        world.send_message(self.pair.local, self.pair.remote, Protocol::Tcp(segment));
+    fn send(&self, world: &mut World, segment: Segment) -> Result<()> {
+        world.send_message(self.pair.local, self.pair.remote, Protocol::Tcp(segment))
     }
 }
 
@@ -292,13 +1200,45 @@ impl AsyncRead for TcpStream {
     }
 }
 
+impl AsyncBufRead for ReadHalf {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<&[u8]>> {
+        self.get_mut().poll_fill_buf_priv(cx)
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        self.get_mut().consume_priv(amt)
+    }
+}
+
+impl AsyncBufRead for TcpStream {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<&[u8]>> {
+        Pin::new(&mut self.get_mut().read_half).poll_fill_buf(cx)
+    }
+
+    fn consume(mut self: Pin<&mut Self>, amt: usize) {
+        Pin::new(&mut self.read_half).consume(amt)
+    }
+}
+
 impl AsyncWrite for WriteHalf {
     fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
         self.poll_write_priv(cx, buf)
     }
 
-    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<()>> {
-        Poll::Ready(Ok(()))
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<Result<usize>> {
+        self.poll_write_vectored_priv(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        true
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.poll_flush_priv(cx)
     }
 
     fn poll_shutdown(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<()>> {
@@ -315,6 +1255,18 @@ impl AsyncWrite for TcpStream {
         Pin::new(&mut self.write_half).poll_write(cx, buf)
     }
 
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<Result<usize>> {
+        Pin::new(&mut self.write_half).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.write_half.is_write_vectored()
+    }
+
     fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
         Pin::new(&mut self.write_half).poll_flush(cx)
     }
@@ -337,8 +1289,14 @@ impl Drop for WriteHalf {
         World::current_if_set(|world| {
             let pair = *self.pair;
 
+            // The FIN was already sent on shutdown
+            if self.is_shutdown {
+                world.current_host_mut().tcp.close_stream_half(pair);
+                return;
+            }
+
             if let Some(seq) = world.current_host_mut().tcp.assign_send_seq(pair) {
-                self.send(world, Segment::Fin(seq));
+                let _ = self.send(world, Segment::Fin(seq));
                 world.current_host_mut().tcp.close_stream_half(pair);
             }
         })
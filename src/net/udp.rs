@@ -1,5 +1,5 @@
-use bytes::Bytes;
-use tokio::sync::{mpsc, Mutex};
+use bytes::{BufMut, Bytes, BytesMut};
+use tokio::sync::{mpsc, Mutex, MutexGuard};
 
 use crate::{
     envelope::{Datagram, Protocol},
@@ -8,31 +8,123 @@ use crate::{
 
 use std::{
     cmp,
-    io::{self, Result},
-    net::SocketAddr,
+    io::{self, IoSlice, Result},
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicU8, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
 };
 
+/// The default receive buffer size of a socket, in bytes.
+const DEFAULT_RECV_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// The default IP time to live of sent datagrams.
+const DEFAULT_TTL: u32 = 64;
+
 /// A simulated UDP socket.
 ///
 /// All methods must be called from a host within a Turmoil simulation.
 pub struct UdpSocket {
     local_addr: SocketAddr,
     rx: Mutex<Rx>,
+    recv_buffer: Arc<RecvBuffer>,
+    /// IP time to live of sent datagrams, see [`UdpSocket::set_ttl`].
+    ttl: AtomicU32,
+    /// IP type of service of sent datagrams, see [`UdpSocket::set_tos`].
+    tos: AtomicU8,
+    /// See [`UdpSocket::set_multicast_loop_v4`].
+    multicast_loop_v4: AtomicBool,
+    /// How long a receive may wait in nanoseconds, see
+    /// [`UdpSocket::set_read_timeout`]. Zero timeouts are rejected, so 0 means
+    /// none is set.
+    read_timeout_nanos: AtomicU64,
+}
+
+/// Accounts for the bytes of unread datagrams queued for a socket, which are
+/// limited by the receive buffer size.
+///
+/// Shared between the socket and the host, which reserves space as datagrams
+/// arrive from the network.
+pub(crate) struct RecvBuffer {
+    size: AtomicUsize,
+    queued: AtomicUsize,
+}
+
+impl RecvBuffer {
+    pub(crate) fn new() -> Self {
+        Self {
+            size: AtomicUsize::new(DEFAULT_RECV_BUFFER_SIZE),
+            queued: AtomicUsize::new(0),
+        }
+    }
+
+    /// Reserves space for `len` bytes, returning false if they don't fit.
+    pub(crate) fn reserve(&self, len: usize) -> bool {
+        let queued = self.queued.load(Ordering::Relaxed);
+
+        if queued + len > self.size.load(Ordering::Relaxed) {
+            return false;
+        }
+
+        self.queued.store(queued + len, Ordering::Relaxed);
+        true
+    }
+
+    /// Releases space previously reserved for `len` bytes.
+    pub(crate) fn release(&self, len: usize) {
+        self.queued.fetch_sub(len, Ordering::Relaxed);
+    }
 }
 
+/// Items are (datagram, origin, destination).
 struct Rx {
-    recv: mpsc::Receiver<(Datagram, SocketAddr)>,
+    recv: mpsc::Receiver<(Datagram, SocketAddr, SocketAddr)>,
+    recv_buffer: Arc<RecvBuffer>,
     /// A buffered received message.
     ///
     /// This is used to support the `readable` method, as [`mpsc::Receiver`]
     /// doesn't expose a way to query channel readiness.
-    buffer: Option<(Datagram, SocketAddr)>,
+    buffer: Option<(Datagram, SocketAddr, SocketAddr)>,
 }
 
 impl Rx {
     /// Tries to receive from either the buffered message or the mpsc channel
-    pub fn try_recv_from(&mut self, buf: &mut [u8]) -> Result<(usize, Datagram, SocketAddr)> {
-        let (datagram, origin) = if let Some(datagram) = self.buffer.take() {
+    ///
+    /// Returns the number of bytes read, the datagram, its origin and the
+    /// address it was delivered to.
+    pub fn try_recv_from(
+        &mut self,
+        buf: &mut [u8],
+    ) -> Result<(usize, Datagram, SocketAddr, SocketAddr)> {
+        let (datagram, origin, dst) = self.try_take()?;
+
+        let bytes = &datagram.0;
+        let limit = cmp::min(buf.len(), bytes.len());
+
+        buf[..limit].copy_from_slice(&bytes[..limit]);
+
+        Ok((limit, datagram, origin, dst))
+    }
+
+    /// Like [`Rx::try_recv_from`], but appends to `buf`.
+    pub fn try_recv_buf<B: BufMut>(
+        &mut self,
+        buf: &mut B,
+    ) -> Result<(usize, Datagram, SocketAddr, SocketAddr)> {
+        let (datagram, origin, dst) = self.try_take()?;
+
+        let bytes = &datagram.0;
+        let limit = cmp::min(buf.remaining_mut(), bytes.len());
+
+        buf.put_slice(&bytes[..limit]);
+
+        Ok((limit, datagram, origin, dst))
+    }
+
+    fn try_take(&mut self) -> Result<(Datagram, SocketAddr, SocketAddr)> {
+        let (datagram, origin, dst) = if let Some(datagram) = self.buffer.take() {
             datagram
         } else {
             self.recv.try_recv().map_err(|_| {
@@ -40,12 +132,9 @@ impl Rx {
             })?
         };
 
-        let bytes = &datagram.0;
-        let limit = cmp::min(buf.len(), bytes.len());
-
-        buf[..limit].copy_from_slice(&bytes[..limit]);
+        self.recv_buffer.release(datagram.0.len());
 
-        Ok((limit, datagram, origin))
+        Ok((datagram, origin, dst))
     }
 
     /// Waits for the socket to become readable.
@@ -80,31 +169,41 @@ impl Rx {
 }
 
 impl UdpSocket {
-    pub(crate) fn new(local_addr: SocketAddr, rx: mpsc::Receiver<(Datagram, SocketAddr)>) -> Self {
+    pub(crate) fn new(
+        local_addr: SocketAddr,
+        rx: mpsc::Receiver<(Datagram, SocketAddr, SocketAddr)>,
+        recv_buffer: Arc<RecvBuffer>,
+    ) -> Self {
         Self {
             local_addr,
             rx: Mutex::new(Rx {
                 recv: rx,
+                recv_buffer: recv_buffer.clone(),
                 buffer: None,
             }),
+            recv_buffer,
+            ttl: AtomicU32::new(DEFAULT_TTL),
+            tos: AtomicU8::new(0),
+            multicast_loop_v4: AtomicBool::new(true),
+            read_timeout_nanos: AtomicU64::new(0),
         }
     }
 
     /// Create a new simulated UDP socket and attempt to bind it to the `addr`
     /// provided.
     ///
-    /// Only 0.0.0.0 is currently supported.
+    /// Only the unspecified address, or one of the host's own addresses (see
+    /// [`Sim::add_host_interface`](crate::Sim::add_host_interface)), is
+    /// currently supported. Binding to 0.0.0.0 receives messages sent to any of
+    /// the host's addresses. Binding to `::` on a host with an IPv6 address
+    /// receives only IPv6 messages, and takes precedence over 0.0.0.0 for
+    /// those.
     pub async fn bind<A: ToSocketAddrs>(addr: A) -> Result<UdpSocket> {
         World::current(|world| {
-            let mut addr = addr.to_socket_addr(&world.dns);
+            let addr = addr.to_socket_addr(&world.dns);
             let host = world.current_host_mut();
-
-            if !addr.ip().is_unspecified() {
-                panic!("{addr} is not supported");
-            }
-
-            // Unspecified -> host's IP
-            addr.set_ip(host.addr);
+            let addr = host.bind_addr(addr);
+            host.check_open_files()?;
 
             host.udp.bind(addr)
         })
@@ -130,17 +229,41 @@ impl UdpSocket {
     /// [`tokio::select!`](crate::select) statement and some other branch
     /// completes first, then it is guaranteed that the message was not sent.
     pub async fn send_to<A: ToSocketAddrs>(&self, buf: &[u8], target: A) -> Result<usize> {
-        World::current(|world| {
-            let dst = target.to_socket_addr(&world.dns);
+        self.send_datagram(Bytes::copy_from_slice(buf), target)
+    }
 
-            world.send_message(
-                self.local_addr,
-                dst,
-                Protocol::Udp(Datagram(Bytes::copy_from_slice(buf))),
-            );
+    /// Sends the concatenation of `bufs` on the socket to the given address,
+    /// as a single datagram, simulating `sendmsg` with an `iovec`. On success,
+    /// returns the number of bytes written.
+    ///
+    /// Otherwise the same as [`send_to`](Self::send_to).
+    pub async fn send_to_vectored<A: ToSocketAddrs>(
+        &self,
+        bufs: &[IoSlice<'_>],
+        target: A,
+    ) -> Result<usize> {
+        let mut bytes = BytesMut::with_capacity(bufs.iter().map(|b| b.len()).sum());
+        for buf in bufs {
+            bytes.extend_from_slice(buf);
+        }
 
-            Ok(buf.len())
-        })
+        self.send_datagram(bytes.freeze(), target)
+    }
+
+    /// Sends a batch of datagrams, simulating `sendmmsg`. On success, returns
+    /// the number of messages sent.
+    ///
+    /// Each message is sent as if by an individual call to
+    /// [`send_to`](Self::send_to). If sending a message fails after others
+    /// have been sent, the count of messages sent so far is returned.
+    pub async fn send_mmsg(&self, messages: &[(&[u8], SocketAddr)]) -> Result<usize> {
+        for (i, (buf, target)) in messages.iter().enumerate() {
+            if let Err(e) = self.send_to(buf, target).await {
+                return if i == 0 { Err(e) } else { Ok(i) };
+            }
+        }
+
+        Ok(messages.len())
     }
 
     /// Tries to send data on the socket to the given address, but if the send is
@@ -158,16 +281,30 @@ impl UdpSocket {
     ///
     /// [`ErrorKind::WouldBlock`]: std::io::ErrorKind::WouldBlock
     pub fn try_send_to<A: ToSocketAddrs>(&self, buf: &[u8], target: A) -> Result<usize> {
+        self.send_datagram(Bytes::copy_from_slice(buf), target)
+    }
+
+    fn send_datagram<A: ToSocketAddrs>(&self, bytes: Bytes, target: A) -> Result<usize> {
         World::current(|world| {
             let dst = target.to_socket_addr(&world.dns);
+            let len = bytes.len();
+            let datagram = Datagram(bytes, self.tos.load(Ordering::Relaxed));
+
+            if dst.ip().is_multicast() {
+                let multicast_loop = self.multicast_loop_v4.load(Ordering::Relaxed);
+                world.send_multicast(self.local_addr, dst, datagram, multicast_loop);
 
-            world.send_message(
+                return Ok(len);
+            }
+
+            world.send_message_with_ttl(
                 self.local_addr,
                 dst,
-                Protocol::Udp(Datagram(Bytes::copy_from_slice(buf))),
-            );
+                self.ttl.load(Ordering::Relaxed),
+                Protocol::Udp(datagram),
+            )?;
 
-            Ok(buf.len())
+            Ok(len)
         })
     }
 
@@ -197,11 +334,23 @@ impl UdpSocket {
     /// to hold the message bytes. If a message is too long to fit in the
     /// supplied buffer, excess bytes may be discarded.
     pub async fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr)> {
-        let mut rx = self.rx.lock().await;
-        rx.readable().await?;
+        let (limit, origin, _) = self.recv_from_with_dst(buf).await?;
 
-        let (limit, datagram, origin) = rx
-            .try_recv_from(buf)
+        Ok((limit, origin))
+    }
+
+    /// Receives a single datagram message on the socket, appending it to
+    /// `buf`. On success, returns the number of bytes read and the origin.
+    ///
+    /// This is the equivalent of tokio's `recv_buf_from`. Unlike
+    /// [`recv_from`](Self::recv_from), the buffer does not need to be
+    /// initialized up front. If a message is too long to fit in the remaining
+    /// capacity of `buf`, excess bytes are discarded.
+    pub async fn recv_buf<B: BufMut>(&self, buf: &mut B) -> Result<(usize, SocketAddr)> {
+        let mut rx = self.lock_readable().await?;
+
+        let (limit, datagram, origin, _) = rx
+            .try_recv_buf(buf)
             .expect("queue should be ready after readable yields");
 
         tracing::trace!(target: TRACING_TARGET, dst = ?self.local_addr, src = ?origin, protocol = %datagram, "Recv");
@@ -209,6 +358,69 @@ impl UdpSocket {
         Ok((limit, origin))
     }
 
+    /// Receives a single datagram message on the socket. On success, returns
+    /// the number of bytes read, the origin and the destination ip address the
+    /// datagram was delivered to.
+    ///
+    /// This simulates `IP_PKTINFO`, which is useful for replying from the same
+    /// address a request was received on.
+    pub async fn recv_from_with_dst(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr, IpAddr)> {
+        let mut rx = self.lock_readable().await?;
+
+        let (limit, datagram, origin, dst) = rx
+            .try_recv_from(buf)
+            .expect("queue should be ready after readable yields");
+
+        tracing::trace!(target: TRACING_TARGET, dst = ?self.local_addr, src = ?origin, protocol = %datagram, "Recv");
+
+        Ok((limit, origin, dst.ip()))
+    }
+
+    /// Receives a batch of datagrams, simulating `recvmmsg`. On success, returns
+    /// the number of bytes read and the origin of each message received.
+    ///
+    /// Waits until at least one datagram is available, then fills as many of
+    /// `bufs` as possible without waiting further. If a message is too long to
+    /// fit in its buffer, excess bytes may be discarded.
+    pub async fn recv_mmsg(&self, bufs: &mut [&mut [u8]]) -> Result<Vec<(usize, SocketAddr)>> {
+        let mut received = vec![];
+
+        if bufs.is_empty() {
+            return Ok(received);
+        }
+
+        let mut rx = self.lock_readable().await?;
+
+        for buf in bufs.iter_mut() {
+            let Ok((limit, datagram, origin, _)) = rx.try_recv_from(buf) else {
+                break;
+            };
+
+            tracing::trace!(target: TRACING_TARGET, dst = ?self.local_addr, src = ?origin, protocol = %datagram, "Recv");
+
+            received.push((limit, origin));
+        }
+
+        Ok(received)
+    }
+
+    /// Waits for a datagram to receive, failing with `TimedOut` once the read
+    /// timeout elapses without one arriving.
+    async fn lock_readable(&self) -> Result<MutexGuard<'_, Rx>> {
+        let readable = async {
+            let mut rx = self.rx.lock().await;
+            rx.readable().await?;
+            Ok(rx)
+        };
+
+        match self.read_timeout()? {
+            Some(timeout) => tokio::time::timeout(timeout, readable)
+                .await
+                .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "read timed out"))?,
+            None => readable.await,
+        }
+    }
+
     /// Tries to receive a single datagram message on the socket. On success,
     /// returns the number of bytes read and the origin.
     ///
@@ -226,7 +438,7 @@ impl UdpSocket {
             )
         })?;
 
-        let (limit, datagram, origin) = rx.try_recv_from(buf).map_err(|_| {
+        let (limit, datagram, origin, _) = rx.try_recv_from(buf).map_err(|_| {
             io::Error::new(io::ErrorKind::WouldBlock, "socket receive queue is empty")
         })?;
 
@@ -275,6 +487,191 @@ impl UdpSocket {
     pub fn local_addr(&self) -> Result<SocketAddr> {
         Ok(self.local_addr)
     }
+
+    /// Sets the size of the socket's receive buffer (`SO_RCVBUF`), in bytes.
+    ///
+    /// Datagrams that arrive while the unread datagrams already queued would
+    /// exceed this size are silently dropped. Defaults to 1 MiB.
+    pub fn set_recv_buffer_size(&self, bytes: usize) -> Result<()> {
+        self.recv_buffer.size.store(bytes, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Sets the read timeout (`SO_RCVTIMEO`) of the socket.
+    ///
+    /// Once set, a receive that doesn't get a datagram within `timeout` of
+    /// simulated time fails with [`io::ErrorKind::TimedOut`]. The timeout
+    /// restarts with each receive. `None` disables the timeout, and a zero
+    /// duration is rejected with [`io::ErrorKind::InvalidInput`].
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<()> {
+        if timeout == Some(Duration::ZERO) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot set a 0 duration timeout",
+            ));
+        }
+
+        let nanos = timeout.map_or(0, |t| t.as_nanos().min(u64::MAX as u128) as u64);
+        self.read_timeout_nanos.store(nanos, Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    /// Returns the read timeout of the socket, see
+    /// [`UdpSocket::set_read_timeout`].
+    pub fn read_timeout(&self) -> Result<Option<Duration>> {
+        let nanos = self.read_timeout_nanos.load(Ordering::Relaxed);
+        Ok((nanos != 0).then(|| Duration::from_nanos(nanos)))
+    }
+
+    /// Gets the value of the `IP_TTL` option for this socket.
+    ///
+    /// For more information about this option, see [`set_ttl`].
+    ///
+    /// [`set_ttl`]: method@Self::set_ttl
+    pub fn ttl(&self) -> Result<u32> {
+        Ok(self.ttl.load(Ordering::Relaxed))
+    }
+
+    /// Sets the value for the `IP_TTL` option on this socket.
+    ///
+    /// This value sets the time-to-live field that is used in every packet
+    /// sent from this socket. Each router a datagram passes through, see
+    /// [`Sim::add_router`](crate::Sim::add_router), decrements it, and the
+    /// datagram is dropped once it reaches zero. Defaults to 64, and must be
+    /// within `1..=255`.
+    pub fn set_ttl(&self, ttl: u32) -> Result<()> {
+        if !(1..=255).contains(&ttl) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid ttl: {ttl}"),
+            ));
+        }
+
+        self.ttl.store(ttl, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Gets the value of the `IP_TOS` option for this socket.
+    ///
+    /// For more information about this option, see [`set_tos`].
+    ///
+    /// [`set_tos`]: method@Self::set_tos
+    pub fn tos(&self) -> Result<u8> {
+        Ok(self.tos.load(Ordering::Relaxed))
+    }
+
+    /// Sets the value for the `IP_TOS` option on this socket.
+    ///
+    /// This value sets the type-of-service field, which holds the DSCP and ECN
+    /// bits, in every packet sent from this socket. Defaults to 0.
+    ///
+    /// The simulated network does not act on the field itself, but it is
+    /// carried by each datagram, see [`Datagram::tos`], so a test may inspect
+    /// it while the message is in flight, e.g. to deliver prioritized traffic
+    /// first with [`Sim::links`](crate::Sim::links).
+    pub fn set_tos(&self, tos: u8) -> Result<()> {
+        self.tos.store(tos, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Gets the value of the `IP_MULTICAST_LOOP` option for this socket.
+    ///
+    /// For more information about this option, see
+    /// [`set_multicast_loop_v4`].
+    ///
+    /// [`set_multicast_loop_v4`]: method@Self::set_multicast_loop_v4
+    pub fn multicast_loop_v4(&self) -> Result<bool> {
+        Ok(self.multicast_loop_v4.load(Ordering::Relaxed))
+    }
+
+    /// Sets the value of the `IP_MULTICAST_LOOP` option for this socket.
+    ///
+    /// If enabled, multicast packets sent from this socket will be looped
+    /// back to sockets on the same host that have joined the group, see
+    /// [`join_multicast_v4`]. Defaults to true.
+    ///
+    /// [`join_multicast_v4`]: method@Self::join_multicast_v4
+    pub fn set_multicast_loop_v4(&self, on: bool) -> Result<()> {
+        self.multicast_loop_v4.store(on, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Executes an operation of the `IP_ADD_MEMBERSHIP` type.
+    ///
+    /// The socket receives datagrams sent to `multiaddr` on the port it is
+    /// bound to, from any host in the simulation. `interface` is ignored, as
+    /// every host is directly connected to every other; routers do not
+    /// forward multicast datagrams.
+    ///
+    /// Returns an error if `multiaddr` is not a multicast address, or the
+    /// socket has already joined the group.
+    pub fn join_multicast_v4(&self, multiaddr: Ipv4Addr, interface: Ipv4Addr) -> Result<()> {
+        let _ = interface;
+
+        World::current(|world| {
+            world
+                .current_host_mut()
+                .udp
+                .join_multicast_v4(self.local_addr, multiaddr)
+        })
+    }
+
+    /// Executes an operation of the `IP_DROP_MEMBERSHIP` type.
+    ///
+    /// For more information about this option, see [`join_multicast_v4`].
+    ///
+    /// Returns an error if the socket has not joined the group.
+    ///
+    /// [`join_multicast_v4`]: method@Self::join_multicast_v4
+    pub fn leave_multicast_v4(&self, multiaddr: Ipv4Addr, interface: Ipv4Addr) -> Result<()> {
+        let _ = interface;
+
+        World::current(|world| {
+            world
+                .current_host_mut()
+                .udp
+                .leave_multicast_v4(self.local_addr, multiaddr)
+        })
+    }
+
+    /// Splits the socket into owned receive and send halves, which can be moved
+    /// to separate tasks.
+    ///
+    /// The socket stays bound until both halves are dropped. Dropping one half
+    /// does not affect the other.
+    pub fn split(self) -> (UdpRecvHalf, UdpSendHalf) {
+        let inner = Arc::new(self);
+
+        (
+            UdpRecvHalf {
+                inner: inner.clone(),
+            },
+            UdpSendHalf { inner },
+        )
+    }
+
+    /// Converting to a [`std::net::UdpSocket`] is not supported, as the socket
+    /// only exists within the simulation. Always returns an
+    /// [`Unsupported`](io::ErrorKind::Unsupported) error.
+    pub fn into_std(self) -> Result<std::net::UdpSocket> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "into_std is not supported for simulated sockets",
+        ))
+    }
+
+    /// Converting from a [`std::net::UdpSocket`] is not supported, as it is
+    /// not part of the simulation. Always returns an
+    /// [`Unsupported`](io::ErrorKind::Unsupported) error.
+    pub fn from_std(socket: std::net::UdpSocket) -> Result<UdpSocket> {
+        drop(socket);
+
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "from_std is not supported for simulated sockets",
+        ))
+    }
 }
 
 impl Drop for UdpSocket {
@@ -282,3 +679,59 @@ impl Drop for UdpSocket {
         World::current_if_set(|world| world.current_host_mut().udp.unbind(self.local_addr));
     }
 }
+
+/// Owned receive half of a [`UdpSocket`], created by [`UdpSocket::split`].
+pub struct UdpRecvHalf {
+    inner: Arc<UdpSocket>,
+}
+
+impl UdpRecvHalf {
+    /// See [`UdpSocket::recv_from`].
+    pub async fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr)> {
+        self.inner.recv_from(buf).await
+    }
+
+    /// See [`UdpSocket::try_recv_from`].
+    pub fn try_recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr)> {
+        self.inner.try_recv_from(buf)
+    }
+
+    /// See [`UdpSocket::readable`].
+    pub async fn readable(&self) -> Result<()> {
+        self.inner.readable().await
+    }
+
+    /// Returns the local address that the socket is bound to.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+}
+
+/// Owned send half of a [`UdpSocket`], created by [`UdpSocket::split`].
+///
+/// Datagrams are sent from the socket's local address.
+pub struct UdpSendHalf {
+    inner: Arc<UdpSocket>,
+}
+
+impl UdpSendHalf {
+    /// See [`UdpSocket::send_to`].
+    pub async fn send_to<A: ToSocketAddrs>(&self, buf: &[u8], target: A) -> Result<usize> {
+        self.inner.send_to(buf, target).await
+    }
+
+    /// See [`UdpSocket::try_send_to`].
+    pub fn try_send_to<A: ToSocketAddrs>(&self, buf: &[u8], target: A) -> Result<usize> {
+        self.inner.try_send_to(buf, target)
+    }
+
+    /// See [`UdpSocket::writable`].
+    pub async fn writable(&self) -> Result<()> {
+        self.inner.writable().await
+    }
+
+    /// Returns the local address that the socket is bound to.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+}
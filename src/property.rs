@@ -0,0 +1,189 @@
+//! Helpers for property based testing.
+//!
+//! A property test runs the same simulation under many rng seeds, so that
+//! each run sees a different, but reproducible, sequence of network hardship.
+//! These helpers are independent of any particular property testing crate;
+//! seeds may come from a range, or be generated by `quickcheck` or `proptest`.
+
+use crate::{Builder, Sim};
+
+use rand::rngs::SmallRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::net::IpAddr;
+use std::panic::{self, AssertUnwindSafe};
+use std::time::Duration;
+
+/// Run `test` once per seed in `seed_range`, each time with a fresh [`Sim`]
+/// from `builder`.
+///
+/// The simulation's rng is seeded from the seed, which is also passed to
+/// `test` for seeding anything else the test randomizes. If the test panics
+/// the failing seed is printed, and the panic is resumed.
+///
+/// ```
+/// let mut builder = turmoil::Builder::new();
+/// builder.fail_rate(0.1);
+///
+/// turmoil::property::run_property_test(&builder, 0..10, |mut sim, _| {
+///     sim.client("client", async { Ok(()) });
+///
+///     sim.run().unwrap();
+/// });
+/// ```
+pub fn run_property_test(
+    builder: &Builder,
+    seed_range: impl Iterator<Item = u64>,
+    test: impl Fn(Sim<'_>, u64),
+) {
+    for seed in seed_range {
+        let sim = builder.build_with_rng(Box::new(SmallRng::seed_from_u64(seed)));
+
+        if let Err(panic) = panic::catch_unwind(AssertUnwindSafe(|| test(sim, seed))) {
+            eprintln!("property test failed with seed: {seed}");
+
+            panic::resume_unwind(panic);
+        }
+    }
+}
+
+/// A partition of `hosts` into two sides, held for a duration, see
+/// [`network_partition_sequence`].
+pub type PartitionStep = (Vec<IpAddr>, Vec<IpAddr>, Duration);
+
+/// An endless, reproducible sequence of network partitions, see
+/// [`network_partition_sequence`].
+pub struct PartitionSequence {
+    rng: SmallRng,
+    hosts: Vec<IpAddr>,
+    max_duration: Duration,
+}
+
+/// Generate partitions of `hosts` from `seed`.
+///
+/// Each step splits all of `hosts` into two non-empty sides, sorted by
+/// address, to be partitioned from each other for a duration in
+/// `1ns..=max_duration`. The sequence never ends, so take as many steps as
+/// the test needs. Panics if there are fewer than two hosts, or if
+/// `max_duration` is zero.
+///
+/// ```
+/// use std::time::Duration;
+/// use turmoil::property::network_partition_sequence;
+///
+/// let mut sim = turmoil::Builder::new().build();
+///
+/// sim.host("a", || async { std::future::pending().await });
+/// sim.host("b", || async { std::future::pending().await });
+/// sim.host("c", || async { std::future::pending().await });
+///
+/// let hosts = ["a", "b", "c"].map(|host| sim.lookup(host));
+/// let steps = network_partition_sequence(42, hosts, Duration::from_millis(100));
+///
+/// for (a, b, duration) in steps.take(10) {
+///     sim.partition(a.clone(), b.clone());
+///     sim.run_for(duration).unwrap();
+///     sim.repair(a, b);
+/// }
+/// ```
+pub fn network_partition_sequence(
+    seed: u64,
+    hosts: impl IntoIterator<Item = IpAddr>,
+    max_duration: Duration,
+) -> PartitionSequence {
+    let hosts = hosts.into_iter().collect::<Vec<_>>();
+
+    assert!(hosts.len() >= 2, "at least two hosts are required");
+    assert!(!max_duration.is_zero(), "max duration must be non-zero");
+
+    PartitionSequence {
+        rng: SmallRng::seed_from_u64(seed),
+        hosts,
+        max_duration,
+    }
+}
+
+impl Iterator for PartitionSequence {
+    type Item = PartitionStep;
+
+    fn next(&mut self) -> Option<PartitionStep> {
+        let mut a = self.hosts.clone();
+        a.shuffle(&mut self.rng);
+
+        let split = self.rng.gen_range(1..a.len());
+        let mut b = a.split_off(split);
+        a.sort();
+        b.sort();
+
+        let max_nanos = self.max_duration.as_nanos().min(u64::MAX as u128) as u64;
+        let duration = Duration::from_nanos(self.rng.gen_range(1..=max_nanos));
+
+        Some((a, b, duration))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cell::RefCell, net::IpAddr, time::Duration};
+
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+    use crate::{
+        property::{network_partition_sequence, run_property_test},
+        Builder,
+    };
+
+    #[test]
+    fn runs_each_seed() {
+        let seeds = RefCell::new(vec![]);
+
+        run_property_test(&Builder::new(), 0..5, |mut sim, seed| {
+            sim.client("client", async { Ok(()) });
+            sim.run().unwrap();
+
+            seeds.borrow_mut().push(seed);
+        });
+
+        assert_eq!(vec![0, 1, 2, 3, 4], seeds.into_inner());
+    }
+
+    #[test]
+    fn seeds_sim() {
+        run_property_test(&Builder::new(), 0..5, |sim, seed| {
+            let expected = SmallRng::seed_from_u64(seed).gen::<u64>();
+            assert_eq!(expected, sim.world.borrow_mut().rng.gen::<u64>());
+        });
+    }
+
+    #[test]
+    fn partition_sequence() {
+        let hosts = (1..=5)
+            .map(|i| IpAddr::from([10, 0, 0, i]))
+            .collect::<Vec<_>>();
+        let max = Duration::from_secs(1);
+
+        let steps = network_partition_sequence(7, hosts.clone(), max)
+            .take(100)
+            .collect::<Vec<_>>();
+
+        for (a, b, duration) in &steps {
+            assert!(!a.is_empty() && !b.is_empty());
+            assert!(a.iter().all(|addr| !b.contains(addr)));
+            assert_eq!(hosts.len(), a.len() + b.len());
+            assert!(!duration.is_zero() && *duration <= max);
+        }
+
+        let again = network_partition_sequence(7, hosts, max).take(100);
+        assert!(steps.into_iter().eq(again));
+    }
+
+    #[test]
+    #[should_panic(expected = "boom")]
+    fn resumes_panic() {
+        run_property_test(&Builder::new(), 0..5, |_, seed| {
+            if seed == 3 {
+                panic!("boom");
+            }
+        });
+    }
+}
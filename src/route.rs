@@ -0,0 +1,140 @@
+use std::fmt::Display;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// An IP network, described by an address and a prefix length, e.g.
+/// `10.0.0.0/8`.
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub struct IpNet {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+/// A static route configured on a router.
+///
+/// Packets arriving at the router destined for an address in `prefix` are
+/// forwarded to `next_hop` if it is another router. Otherwise `next_hop` must
+/// be in `prefix`, typically the destination host itself, and packets are
+/// forwarded straight to their destination.
+#[derive(Debug, Clone)]
+pub struct Route {
+    /// The destination network matched by this route.
+    pub prefix: IpNet,
+
+    /// Where matching packets are forwarded to.
+    pub next_hop: IpAddr,
+
+    /// Additional latency added when forwarding a packet.
+    pub latency: Duration,
+}
+
+impl IpNet {
+    /// Create a new network. Panics if `prefix_len` is longer than the address.
+    pub fn new(addr: IpAddr, prefix_len: u8) -> IpNet {
+        assert!(
+            prefix_len <= max_prefix_len(addr),
+            "invalid prefix length {prefix_len} for {addr}"
+        );
+
+        IpNet { addr, prefix_len }
+    }
+
+    /// The network address.
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    /// The number of leading bits that identify the network.
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    /// Whether `addr` belongs to this network.
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match (self.addr, addr) {
+            (IpAddr::V4(net), IpAddr::V4(addr)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u32::from(net) & mask == u32::from(addr) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(addr)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u128::from(net) & mask == u128::from(addr) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+fn max_prefix_len(addr: IpAddr) -> u8 {
+    match addr {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    }
+}
+
+impl From<IpAddr> for IpNet {
+    /// A network containing only `addr`.
+    fn from(addr: IpAddr) -> IpNet {
+        IpNet::new(addr, max_prefix_len(addr))
+    }
+}
+
+impl FromStr for IpNet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<IpNet, String> {
+        let (addr, prefix_len) = s
+            .split_once('/')
+            .ok_or_else(|| format!("missing prefix length: {s}"))?;
+
+        let addr: IpAddr = addr.parse().map_err(|_| format!("invalid address: {s}"))?;
+        let prefix_len: u8 = prefix_len
+            .parse()
+            .map_err(|_| format!("invalid prefix length: {s}"))?;
+
+        if prefix_len > max_prefix_len(addr) {
+            return Err(format!("invalid prefix length: {s}"));
+        }
+
+        Ok(IpNet { addr, prefix_len })
+    }
+}
+
+impl Display for IpNet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::IpNet;
+
+    #[test]
+    fn contains() {
+        let net: IpNet = "10.1.0.0/16".parse().unwrap();
+
+        assert!(net.contains("10.1.2.3".parse().unwrap()));
+        assert!(!net.contains("10.2.0.1".parse().unwrap()));
+        assert!(!net.contains("::1".parse().unwrap()));
+
+        let any: IpNet = "0.0.0.0/0".parse().unwrap();
+        assert!(any.contains("192.168.0.1".parse().unwrap()));
+
+        let v6: IpNet = "fe80::/10".parse().unwrap();
+        assert!(v6.contains("fe80::1".parse().unwrap()));
+        assert!(!v6.contains("::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert!("10.0.0.0".parse::<IpNet>().is_err());
+        assert!("10.0.0.0/33".parse::<IpNet>().is_err());
+        assert!("foo/8".parse::<IpNet>().is_err());
+    }
+}
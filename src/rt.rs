@@ -1,7 +1,8 @@
+use std::any::Any;
 use std::mem;
 
 use super::Result;
-use futures::Future;
+use futures::{Future, FutureExt};
 use std::pin::Pin;
 use tokio::runtime::Runtime;
 use tokio::task::JoinHandle;
@@ -30,7 +31,8 @@ enum Kind<'a> {
 ///
 /// The tokio runtime is paused (see [`Builder::start_paused`]), which gives us
 /// control over when and how to advance time. In particular, see [`Rt::tick`],
-/// which lets the runtime do a bit more work.
+/// which lets the runtime do a bit more work. If simulated time is turned off
+/// with `use_simulated_time`, the runtime follows wall-clock time instead.
 pub(crate) struct Rt<'a> {
     kind: Kind<'a>,
 
@@ -44,14 +46,22 @@ pub(crate) struct Rt<'a> {
     /// Optional handle to a host's software. When software finishes, the handle is
     /// consumed to check for error, which is propagated up to fail the simulation.
     handle: Option<JoinHandle<Result>>,
+
+    /// Simulated time the software has been running since it was last
+    /// started.
+    uptime: Duration,
+
+    /// Whether the tokio clock is paused and driven by [`Rt::tick`], as
+    /// opposed to following wall-clock time.
+    simulated_time: bool,
 }
 
 impl<'a> Rt<'a> {
-    pub(crate) fn client<F>(client: F) -> Self
+    pub(crate) fn client<F>(client: F, simulated_time: bool) -> Self
     where
         F: Future<Output = Result> + 'static,
     {
-        let (tokio, local) = init();
+        let (tokio, local) = init(simulated_time);
 
         let handle = with(&tokio, &local, || tokio::task::spawn_local(client));
 
@@ -60,15 +70,17 @@ impl<'a> Rt<'a> {
             tokio,
             local,
             handle: Some(handle),
+            uptime: Duration::ZERO,
+            simulated_time,
         }
     }
 
-    pub(crate) fn host<F, Fut>(software: F) -> Self
+    pub(crate) fn host<F, Fut>(software: F, simulated_time: bool) -> Self
     where
         F: Fn() -> Fut + 'a,
         Fut: Future<Output = Result> + 'static,
     {
-        let (tokio, local) = init();
+        let (tokio, local) = init(simulated_time);
 
         let software: Software = Box::new(move || Box::pin(software()));
         let handle = with(&tokio, &local, || tokio::task::spawn_local(software()));
@@ -78,17 +90,21 @@ impl<'a> Rt<'a> {
             tokio,
             local,
             handle: Some(handle),
+            uptime: Duration::ZERO,
+            simulated_time,
         }
     }
 
     pub(crate) fn no_software() -> Self {
-        let (tokio, local) = init();
+        let (tokio, local) = init(true);
 
         Self {
             kind: Kind::NoSoftware,
             tokio,
             local,
             handle: None,
+            uptime: Duration::ZERO,
+            simulated_time: true,
         }
     }
 
@@ -96,7 +112,7 @@ impl<'a> Rt<'a> {
         matches!(self.kind, Kind::Client)
     }
 
-    fn is_host(&self) -> bool {
+    pub(crate) fn is_host(&self) -> bool {
         matches!(self.kind, Kind::Host { .. })
     }
 
@@ -104,6 +120,10 @@ impl<'a> Rt<'a> {
         self.handle.is_some()
     }
 
+    pub(crate) fn uptime(&self) -> Duration {
+        self.uptime
+    }
+
     pub(crate) fn now(&self) -> Instant {
         let _guard = self.tokio.enter();
         Instant::now()
@@ -135,11 +155,21 @@ impl<'a> Rt<'a> {
         self.tokio.block_on(async {
             self.local
                 .run_until(async {
-                    sleep(duration).await;
+                    // A zero sleep completes without giving other tasks a
+                    // chance to run, when time is frozen yield instead.
+                    if duration.is_zero() {
+                        tokio::task::yield_now().await;
+                    } else {
+                        sleep(duration).await;
+                    }
                 })
                 .await
         });
 
+        if self.handle.is_some() {
+            self.uptime += duration;
+        }
+
         // pull for software completion
         match &self.handle {
             Some(handle) if handle.is_finished() => {
@@ -159,6 +189,19 @@ impl<'a> Rt<'a> {
         }
     }
 
+    /// Spawn a task alongside the software, which runs as the runtime ticks.
+    ///
+    /// The task is cancelled if the runtime's software is crashed or bounced.
+    pub(crate) fn spawn<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        with(&self.tokio, &self.local, || {
+            tokio::task::spawn_local(future)
+        })
+    }
+
     pub(crate) fn crash(&mut self) {
         if !self.is_host() {
             panic!("can only crash host's software");
@@ -167,6 +210,27 @@ impl<'a> Rt<'a> {
         if self.handle.take().is_some() {
             self.cancel_tasks();
         };
+
+        self.uptime = Duration::ZERO;
+    }
+
+    /// The payload the software panicked with, if it has.
+    pub(crate) fn take_panic(&mut self) -> Option<Box<dyn Any + Send>> {
+        let handle = self.handle.as_mut().filter(|h| h.is_finished())?;
+
+        match handle.now_or_never()? {
+            Err(e) if e.is_panic() => {
+                self.handle = None;
+                Some(e.into_panic())
+            }
+            _ => None,
+        }
+    }
+
+    /// Stop the software and its tasks, whether a client or a host.
+    pub(crate) fn abort(&mut self) {
+        self.handle = None;
+        self.cancel_tasks();
     }
 
     pub(crate) fn bounce(&mut self) {
@@ -182,6 +246,8 @@ impl<'a> Rt<'a> {
             });
             self.handle.replace(handle);
         };
+
+        self.uptime = Duration::ZERO;
     }
 
     /// Cancel all tasks within the [`Rt`] by dropping the current tokio
@@ -193,20 +259,24 @@ impl<'a> Rt<'a> {
     ///
     /// Both the [`Runtime`] and [`LocalSet`] are replaced with new instances.
     fn cancel_tasks(&mut self) {
-        let (tokio, local) = init();
+        let (tokio, local) = init(self.simulated_time);
 
         _ = mem::replace(&mut self.tokio, tokio);
         drop(mem::replace(&mut self.local, local));
     }
 }
 
-fn init() -> (Runtime, LocalSet) {
+fn init(simulated_time: bool) -> (Runtime, LocalSet) {
     let mut builder = tokio::runtime::Builder::new_current_thread();
 
     #[cfg(tokio_unstable)]
     builder.unhandled_panic(tokio::runtime::UnhandledPanic::ShutdownRuntime);
 
-    let tokio = builder.enable_time().start_paused(true).build().unwrap();
+    let tokio = builder
+        .enable_time()
+        .start_paused(simulated_time)
+        .build()
+        .unwrap();
 
     tokio.block_on(async {
         // Sleep to "round" `Instant::now()` to the closest `ms`
@@ -1,14 +1,21 @@
-use crate::envelope::{Envelope, Protocol};
+use crate::envelope::{Datagram, Envelope, Protocol};
 use crate::host::Host;
 use crate::rt::Rt;
-use crate::{config, TRACING_TARGET};
+use crate::{config, FlakyLinkConfig, IpNet, Route, TRACING_TARGET};
 
-use indexmap::IndexMap;
+use bytes::Bytes;
+use indexmap::{IndexMap, IndexSet};
 use rand::{Rng, RngCore};
 use rand_distr::{Distribution, Exp};
+use std::cell::RefCell;
 use std::collections::VecDeque;
+use std::fmt::Write;
+use std::io;
+use std::mem;
 use std::net::{IpAddr, SocketAddr};
-use std::time::Duration;
+use std::rc::{Rc, Weak};
+use std::task::Waker;
+use std::time::{Duration, SystemTime};
 use tokio::time::Instant;
 
 /// Describes the network topology.
@@ -18,6 +25,42 @@ pub(crate) struct Topology {
     /// Specific configuration overrides between specific hosts.
     links: IndexMap<Pair, Link>,
 
+    /// Static routes, keyed by router address. Kept in registration order,
+    /// which determines the first hop for routed messages.
+    routers: IndexMap<IpAddr, Vec<Route>>,
+
+    /// Network address translators, keyed by external address.
+    nats: IndexMap<IpAddr, Nat>,
+
+    /// Receive throughput limits, keyed by host address.
+    rx_limits: IndexMap<IpAddr, RxLimit>,
+
+    /// Maps additional host addresses to the host's primary address, which
+    /// links are registered by.
+    interfaces: IndexMap<IpAddr, IpAddr>,
+
+    /// Addresses of hosts removed from the simulation, to which messages go
+    /// nowhere rather than failing as unreachable.
+    removed: IndexSet<IpAddr>,
+
+    /// Probability of dropping each message, keyed by (src, dst) host.
+    message_loss: IndexMap<(IpAddr, IpAddr), f64>,
+
+    /// (src, dst) hosts between which messages are dropped in one direction
+    /// only.
+    one_way_partitions: IndexSet<(IpAddr, IpAddr)>,
+
+    /// History of messages that have been delivered or dropped.
+    pub(crate) log: MessageLog,
+
+    /// Links between two hosts whose messages are recorded, see
+    /// [`LinkRecorder`].
+    recorders: Vec<(IpAddr, IpAddr, Weak<Packets>)>,
+
+    /// Tasks waiting for messages in flight to be delivered, woken as the
+    /// network ticks.
+    flush_waiters: Vec<Waker>,
+
     /// We don't use a Rt for async. Right now, we just use it to tick time
     /// forward in the same way we do it elsewhere. We'd like to represent
     /// network state with async in the future.
@@ -130,6 +173,17 @@ impl<'a> Iterator for LinkIter<'a> {
     }
 }
 
+/// Fault configuration saved by [`Topology::faults`].
+pub(crate) struct Faults {
+    config: config::Link,
+    links: IndexMap<Pair, config::Link>,
+    message_loss: IndexMap<(IpAddr, IpAddr), f64>,
+}
+
+/// Returns the delivery priority of a message, where 0 is the highest, see
+/// [`crate::Sim::set_link_priority_queue`].
+pub(crate) type PriorityFn = Box<dyn Fn(&Protocol) -> u8>;
+
 /// A two-way link between two hosts on the network.
 struct Link {
     state: State,
@@ -146,6 +200,14 @@ struct Link {
 
     /// The current network time, moved forward with [`Link::tick`].
     now: Instant,
+
+    /// When the link finishes transmitting the messages already sent toward
+    /// each end, if the link's bandwidth is limited.
+    busy_until: IndexMap<IpAddr, Instant>,
+
+    /// Orders deliverable messages toward each end of the link by priority,
+    /// lowest first, rather than by arrival.
+    priorities: IndexMap<IpAddr, PriorityFn>,
 }
 
 enum State {
@@ -167,32 +229,178 @@ impl Topology {
         Topology {
             config,
             links: IndexMap::new(),
+            routers: IndexMap::new(),
+            nats: IndexMap::new(),
+            rx_limits: IndexMap::new(),
+            interfaces: IndexMap::new(),
+            removed: IndexSet::new(),
+            message_loss: IndexMap::new(),
+            one_way_partitions: IndexSet::new(),
+            log: MessageLog::default(),
+            recorders: vec![],
+            flush_waiters: vec![],
             rt: Rt::no_software(),
         }
     }
 
+    /// The simulated time elapsed since the simulation started.
+    pub(crate) fn elapsed(&self) -> Duration {
+        self.log.elapsed
+    }
+
+    /// The number of times the network has ticked.
+    pub(crate) fn tick(&self) -> u64 {
+        self.log.tick
+    }
+
+    /// The current network time.
+    pub(crate) fn now(&self) -> Instant {
+        self.rt.now()
+    }
+
+    /// Take `addr` back into use by a newly registered host.
+    pub(crate) fn reuse(&mut self, addr: IpAddr) {
+        self.removed.shift_remove(&addr);
+    }
+
     /// Register a link between two hosts
     pub(crate) fn register(&mut self, a: IpAddr, b: IpAddr) {
         let pair = Pair::new(a, b);
         assert!(self.links.insert(pair, Link::new(self.rt.now())).is_none());
     }
 
+    /// Remove all links to and from `addr`, dropping any in flight messages.
+    pub(crate) fn deregister(&mut self, addr: IpAddr) {
+        self.links
+            .retain(|pair, _| pair.0 != addr && pair.1 != addr);
+        self.routers.shift_remove(&addr);
+        self.nats.shift_remove(&addr);
+        self.rx_limits.shift_remove(&addr);
+
+        let removed = &mut self.removed;
+        removed.insert(addr);
+        self.interfaces.retain(|interface, host| {
+            if *host == addr {
+                removed.insert(*interface);
+            }
+            *host != addr
+        });
+    }
+
+    /// Make the host at `host` reachable at `addr`.
+    pub(crate) fn add_interface(&mut self, host: IpAddr, addr: IpAddr) {
+        assert!(
+            self.interfaces.insert(addr, host).is_none(),
+            "already registered interface for the given ip address"
+        );
+        self.reuse(addr);
+    }
+
+    /// The primary address of the host at `addr`.
+    fn resolve(&self, addr: IpAddr) -> IpAddr {
+        self.interfaces.get(&addr).copied().unwrap_or(addr)
+    }
+
+    /// Register `addr` as a router, forwarding messages based on `routes`.
+    pub(crate) fn add_router(&mut self, addr: IpAddr, routes: Vec<Route>) {
+        for route in &routes {
+            assert!(
+                self.routers.contains_key(&route.next_hop) || route.prefix.contains(route.next_hop),
+                "next hop {} of the route to {} is neither a router nor in the route's prefix",
+                route.next_hop,
+                route.prefix
+            );
+        }
+
+        assert!(
+            self.routers.insert(addr, routes).is_none(),
+            "{addr} is already a router"
+        );
+    }
+
     pub(crate) fn set_max_message_latency(&mut self, value: Duration) {
         self.config.latency_mut().max_message_latency = value;
     }
 
     pub(crate) fn set_link_message_latency(&mut self, a: IpAddr, b: IpAddr, value: Duration) {
-        let latency = self.links[&Pair::new(a, b)].latency(self.config.latency());
+        let link = &mut self.links[&Pair::new(a, b)];
+        link.config.directional_latency.clear();
+
+        let latency = link.latency(self.config.latency());
         latency.min_message_latency = value;
         latency.max_message_latency = value;
     }
 
+    /// Fix the latency of messages from `a` to `b` and from `b` to `a`
+    /// independently.
+    pub(crate) fn set_link_directional_latency(
+        &mut self,
+        a: IpAddr,
+        b: IpAddr,
+        a_to_b: Duration,
+        b_to_a: Duration,
+    ) {
+        let directional = &mut self.links[&Pair::new(a, b)].config.directional_latency;
+        directional.insert(b, a_to_b);
+        directional.insert(a, b_to_a);
+    }
+
+    /// Corrupt messages from `src` to `dst`, see
+    /// [`Sim::enable_packet_corruption`](crate::Sim::enable_packet_corruption).
+    pub(crate) fn set_link_corruption(
+        &mut self,
+        src: IpAddr,
+        dst: IpAddr,
+        corruption: config::Corruption,
+    ) {
+        self.links[&Pair::new(src, dst)]
+            .config
+            .corruption
+            .insert(dst, corruption);
+    }
+
     pub(crate) fn set_link_max_message_latency(&mut self, a: IpAddr, b: IpAddr, value: Duration) {
         self.links[&Pair::new(a, b)]
             .latency(self.config.latency())
             .max_message_latency = value;
     }
 
+    /// Apply all of the faults in `flaky` to the link between `a` and `b`, in
+    /// both directions.
+    pub(crate) fn set_link_flaky(&mut self, a: IpAddr, b: IpAddr, flaky: &FlakyLinkConfig) {
+        let link = &mut self.links[&Pair::new(a, b)];
+        link.config.directional_latency.clear();
+        link.config.duplication_rate = flaky.duplication;
+        link.config.reorder_rate = flaky.reorder;
+        link.config.bandwidth = (flaky.bandwidth > 0).then_some(flaky.bandwidth);
+
+        let latency = link.latency(self.config.latency());
+        latency.min_message_latency = flaky.latency_base;
+        latency.max_message_latency = flaky.latency_base + flaky.latency_jitter;
+
+        let loss = (flaky.loss > 0.0).then_some(flaky.loss);
+        self.set_message_loss(a, b, loss);
+        self.set_message_loss(b, a, loss);
+    }
+
+    /// Deliver messages from `src` to `dst` that are ready at the same time in
+    /// the order given by `priority`.
+    pub(crate) fn set_link_priority(&mut self, src: IpAddr, dst: IpAddr, priority: PriorityFn) {
+        self.links[&Pair::new(src, dst)]
+            .priorities
+            .insert(dst, priority);
+    }
+
+    /// The per-link configuration overrides between `a` and `b`.
+    pub(crate) fn link_config(&self, a: IpAddr, b: IpAddr) -> config::Link {
+        self.links[&Pair::new(a, b)].config.clone()
+    }
+
+    /// Replace the per-link configuration overrides between `a` and `b`.
+    pub(crate) fn set_link_config(&mut self, a: IpAddr, b: IpAddr, value: config::Link) {
+        self.links[&Pair::new(a, b)].config = value;
+    }
+
     pub(crate) fn set_message_latency_curve(&mut self, value: f64) {
         self.config.latency_mut().latency_distribution = Exp::new(value).unwrap();
     }
@@ -201,6 +409,47 @@ impl Topology {
         self.config.message_loss_mut().fail_rate = value;
     }
 
+    pub(crate) fn set_repair_rate(&mut self, value: f64) {
+        self.config.message_loss_mut().repair_rate = value;
+    }
+
+    /// Apply all of the faults in `flaky` to every link, see
+    /// [`Topology::set_link_flaky`].
+    pub(crate) fn set_links_flaky(&mut self, flaky: &FlakyLinkConfig) {
+        let pairs = self.links.keys().cloned().collect::<Vec<_>>();
+
+        for Pair(a, b) in pairs {
+            self.set_link_flaky(a, b, flaky);
+        }
+    }
+
+    /// Save the global and per-link fault configuration, to be put back with
+    /// [`Topology::restore_faults`].
+    pub(crate) fn faults(&self) -> Faults {
+        Faults {
+            config: self.config.clone(),
+            links: self
+                .links
+                .iter()
+                .map(|(pair, link)| (pair.clone(), link.config.clone()))
+                .collect(),
+            message_loss: self.message_loss.clone(),
+        }
+    }
+
+    /// Put back fault configuration saved by [`Topology::faults`]. Links
+    /// added since keep their configuration.
+    pub(crate) fn restore_faults(&mut self, faults: Faults) {
+        self.config = faults.config;
+        self.message_loss = faults.message_loss;
+
+        for (pair, config) in faults.links {
+            if let Some(link) = self.links.get_mut(&pair) {
+                link.config = config;
+            }
+        }
+    }
+
     pub(crate) fn set_link_fail_rate(&mut self, a: IpAddr, b: IpAddr, value: f64) {
         self.links[&Pair::new(a, b)]
             .message_loss(self.config.message_loss())
@@ -210,24 +459,455 @@ impl Topology {
     // Send a `message` from `src` to `dst`. This method returns immediately,
     // and message delivery happens at a later time (or never, if the link is
     // broken).
+    //
+    // `latency` is added on top of the link's latency.
+    //
+    // Returns an error if there is neither a route nor a direct link to `dst`,
+    // e.g. because the host has been removed from the simulation.
     pub(crate) fn enqueue_message(
         &mut self,
         rand: &mut dyn RngCore,
         src: SocketAddr,
         dst: SocketAddr,
+        latency: Duration,
         message: Protocol,
+    ) -> io::Result<()> {
+        let dst_host = self.resolve(dst.ip());
+        self.enqueue_message_to(rand, src, dst, dst_host, latency, message)
+    }
+
+    /// Same as [`Topology::enqueue_message`], but the message is delivered to
+    /// the host at `dst_host`, whatever the destination address. Used for
+    /// multicast, where `dst` is the group.
+    pub(crate) fn enqueue_message_to(
+        &mut self,
+        rand: &mut dyn RngCore,
+        src: SocketAddr,
+        dst: SocketAddr,
+        dst_host: IpAddr,
+        latency: Duration,
+        message: Protocol,
+    ) -> io::Result<()> {
+        self.log.sent(src, dst, &message);
+
+        let src_host = self.resolve(src.ip());
+        self.capture(src_host, dst_host, src, dst, &message);
+        let hop = self.first_hop(src_host, dst_host).unwrap_or(dst_host);
+
+        let (protocol, len) = (message.name(), message.len());
+
+        if self.one_way_partitions.contains(&(src_host, dst_host)) {
+            tracing::trace!(target: TRACING_TARGET, ?src, ?dst, protocol = %message, "Drop (Partition)");
+            self.log.record(src, dst, protocol, len, false);
+
+            return Ok(());
+        }
+
+        if let Some(&probability) = self.message_loss.get(&(src_host, dst_host)) {
+            if rand.gen_bool(probability) {
+                tracing::trace!(target: TRACING_TARGET, ?src, ?dst, protocol = %message, "Drop (Loss)");
+                self.log.record(src, dst, protocol, len, false);
+
+                return Ok(());
+            }
+        }
+
+        match self.links.get_mut(&Pair::new(src_host, hop)) {
+            Some(link) => {
+                if !link.enqueue_message(&self.config, rand, src, dst, hop, latency, message) {
+                    self.log.record(src, dst, protocol, len, false);
+                }
+                Ok(())
+            }
+            // The host has been removed from the simulation, so the message
+            // goes nowhere
+            None if self.removed.contains(&dst_host) => {
+                tracing::trace!(target: TRACING_TARGET, ?src, ?dst, protocol = %message, "Drop");
+                self.log.record(src, dst, protocol, len, false);
+
+                Ok(())
+            }
+            None => {
+                tracing::trace!(target: TRACING_TARGET, ?src, ?dst, protocol = %message, "Unreachable");
+                self.log.record(src, dst, protocol, len, false);
+
+                Err(io::Error::new(
+                    io::ErrorKind::HostUnreachable,
+                    dst.ip().to_string(),
+                ))
+            }
+        }
+    }
+
+    /// The probability of dropping each message from `src` to `dst`.
+    pub(crate) fn message_loss(&self, src: IpAddr, dst: IpAddr) -> Option<f64> {
+        self.message_loss.get(&(src, dst)).copied()
+    }
+
+    pub(crate) fn set_message_loss(&mut self, src: IpAddr, dst: IpAddr, value: Option<f64>) {
+        match value {
+            Some(probability) => {
+                self.message_loss.insert((src, dst), probability);
+            }
+            None => {
+                self.message_loss.shift_remove(&(src, dst));
+            }
+        }
+    }
+
+    /// Messages leaving a NAT's internal network go through the NAT.
+    ///
+    /// Otherwise, messages from hosts outside of a route's prefix go through
+    /// the first router, in registration order, that has a route matching
+    /// `dst`.
+    fn first_hop(&self, src: IpAddr, dst: IpAddr) -> Option<IpAddr> {
+        let nat = self.nats.iter().find(|(external, nat)| {
+            **external != dst && nat.internal.contains(src) && !nat.internal.contains(dst)
+        });
+
+        if let Some((external, _)) = nat {
+            return Some(*external);
+        }
+
+        self.routers
+            .iter()
+            .filter(|(router, _)| **router != src && **router != dst)
+            .find(|(_, routes)| {
+                longest_match(routes, dst).is_some_and(|route| !route.prefix.contains(src))
+            })
+            .map(|(router, _)| *router)
+    }
+
+    /// The number of routers a message from `src` to `dst` passes through.
+    pub(crate) fn router_hops(&self, src: IpAddr, dst: IpAddr) -> usize {
+        let (src, dst) = (self.resolve(src), self.resolve(dst));
+
+        let mut hops = 0;
+        let mut hop = self.first_hop(src, dst);
+
+        // Bounded in case routes form a loop.
+        while let Some(routes) = hop.and_then(|hop| self.routers.get(&hop)) {
+            hops += 1;
+
+            if hops > self.routers.len() {
+                break;
+            }
+
+            hop = longest_match(routes, dst)
+                .map(|route| route.next_hop)
+                .filter(|next_hop| self.routers.contains_key(next_hop));
+        }
+
+        hops
+    }
+
+    /// Record messages sent between `a` and `b`, in either direction.
+    pub(crate) fn add_recorder(&mut self, a: IpAddr, b: IpAddr) -> LinkRecorder {
+        let packets = Rc::new(RefCell::new(vec![]));
+        self.recorders.push((a, b, Rc::downgrade(&packets)));
+
+        LinkRecorder { packets }
+    }
+
+    fn capture(
+        &mut self,
+        src_host: IpAddr,
+        dst_host: IpAddr,
+        src: SocketAddr,
+        dst: SocketAddr,
+        message: &Protocol,
     ) {
-        let link = &mut self.links[&Pair::new(src.ip(), dst.ip())];
-        link.enqueue_message(&self.config, rand, src, dst, message);
+        // Stop recording for dropped recorders
+        self.recorders
+            .retain(|(_, _, packets)| packets.strong_count() > 0);
+
+        for (a, b, packets) in &self.recorders {
+            let is_match = (*a, *b) == (src_host, dst_host) || (*b, *a) == (src_host, dst_host);
+
+            if let Some(packets) = packets.upgrade().filter(|_| is_match) {
+                packets.borrow_mut().push(CapturedPacket {
+                    tick: self.log.tick,
+                    simtime: self.log.elapsed,
+                    src,
+                    dst,
+                    protocol: message.name(),
+                    payload: message.payload(),
+                });
+            }
+        }
+    }
+
+    /// Drop a message whose time to live expires before reaching `dst`.
+    pub(crate) fn expire_message(&mut self, src: SocketAddr, dst: SocketAddr, message: Protocol) {
+        tracing::trace!(target: TRACING_TARGET, ?src, ?dst, protocol = %message, "Drop (TTL)");
+        self.log.sent(src, dst, &message);
+        self.log
+            .record(src, dst, message.name(), message.len(), false);
+    }
+
+    /// Register `external` as a NAT for hosts in `internal`.
+    pub(crate) fn add_nat(&mut self, external: IpAddr, internal: IpNet) {
+        assert!(
+            self.nats.insert(external, Nat::new(internal)).is_none(),
+            "{external} is already a NAT"
+        );
+    }
+
+    /// The (internal, external) address mappings of the NAT at `external`.
+    pub(crate) fn nat_table(&self, external: IpAddr) -> Vec<(SocketAddr, SocketAddr)> {
+        let nat = self.nats.get(&external).expect("missing NAT");
+
+        nat.table
+            .iter()
+            .map(|(internal, port)| (*internal, (external, *port).into()))
+            .collect()
+    }
+
+    /// Rewrite messages that have arrived at NATs and send them on.
+    ///
+    /// Outbound messages have their source replaced by the NAT's external
+    /// address, allocating a mapping if necessary. Inbound messages to a mapped
+    /// port are sent on to the internal address.
+    fn translate_messages(&mut self, rand: &mut dyn RngCore) {
+        let mut translated = vec![];
+
+        for (&external, nat) in &mut self.nats {
+            for (pair, link) in &mut self.links {
+                if pair.0 != external && pair.1 != external {
+                    continue;
+                }
+
+                for envelope in link.take_deliverable(external) {
+                    let Envelope { src, dst, message } = envelope;
+
+                    if dst.ip() != external {
+                        match nat.map(src) {
+                            Ok(port) => translated.push(((external, port).into(), dst, message)),
+                            Err(error) => {
+                                tracing::trace!(target: TRACING_TARGET, ?src, ?dst, protocol = %message, %error, "Drop (NAT)");
+                                self.log
+                                    .record(src, dst, message.name(), message.len(), false);
+                            }
+                        }
+                        continue;
+                    }
+
+                    match nat.reverse(dst.port()) {
+                        Some(internal) => translated.push((src, internal, message)),
+                        None => {
+                            tracing::trace!(target: TRACING_TARGET, ?src, ?dst, protocol = %message, "Drop (No NAT mapping)");
+                            self.log
+                                .record(src, dst, message.name(), message.len(), false);
+                        }
+                    }
+                }
+            }
+        }
+
+        for (src, dst, message) in translated {
+            let _ = self.enqueue_message(rand, src, dst, Duration::ZERO, message);
+        }
+    }
+
+    /// Forward messages that have arrived at routers on to their next hop.
+    fn forward_messages(&mut self, rand: &mut dyn RngCore) {
+        for (&router, routes) in &self.routers {
+            let mut arrived = vec![];
+            for (pair, link) in &mut self.links {
+                if pair.0 == router || pair.1 == router {
+                    arrived.extend(link.take_deliverable(router));
+                }
+            }
+
+            for envelope in arrived {
+                let (src, dst) = (envelope.src, envelope.dst);
+                let (protocol, len) = (envelope.message.name(), envelope.message.len());
+
+                let Some(route) = longest_match(routes, dst.ip()) else {
+                    tracing::trace!(target: TRACING_TARGET, ?src, ?dst, protocol = %envelope.message, "Unreachable");
+                    self.log.record(src, dst, protocol, len, false);
+                    continue;
+                };
+
+                // Forward to another router, or otherwise straight to the
+                // destination.
+                let hop = if self.routers.contains_key(&route.next_hop) {
+                    route.next_hop
+                } else {
+                    self.interfaces.get(&dst.ip()).copied().unwrap_or(dst.ip())
+                };
+
+                let is_queued = match self.links.get_mut(&Pair::new(router, hop)) {
+                    Some(link) => link.forward(&self.config, rand, envelope, hop, route.latency),
+                    None => {
+                        tracing::trace!(target: TRACING_TARGET, ?src, ?dst, protocol = %envelope.message, "Unreachable");
+                        false
+                    }
+                };
+
+                if !is_queued {
+                    self.log.record(src, dst, protocol, len, false);
+                }
+            }
+        }
+    }
+
+    /// Limit the rate at which `addr` receives messages to `bytes_per_sec`.
+    pub(crate) fn set_rx_limit(&mut self, addr: IpAddr, bytes_per_sec: u64) {
+        assert!(bytes_per_sec > 0, "rate must be positive");
+
+        let now = self.rt.now();
+        let limit = self.rx_limits.entry(addr).or_insert_with(|| RxLimit {
+            bytes_per_sec,
+            next: now,
+            queue: VecDeque::new(),
+        });
+
+        limit.bytes_per_sec = bytes_per_sec;
     }
 
     // Move messages from any network links to the `dst` host.
+    //
+    // FIXME: This implementation does not respect message delivery order. If
+    // host A and host B are ordered (by addr), and B sends before A, then this
+    // method will deliver A's message before B's.
     pub(crate) fn deliver_messages(&mut self, rand: &mut dyn RngCore, dst: &mut Host) {
+        let mut arrived = vec![];
         for (pair, link) in &mut self.links {
             if pair.0 == dst.addr || pair.1 == dst.addr {
-                link.deliver_messages(&self.config, rand, dst);
+                arrived.extend(link.take_deliverable(dst.addr));
+            }
+        }
+
+        if let Some(limit) = self.rx_limits.get_mut(&dst.addr) {
+            let now = self.rt.now();
+
+            for envelope in arrived.drain(..) {
+                limit.enqueue(now, envelope);
+            }
+
+            while let Some(envelope) = limit.dequeue(now) {
+                arrived.push(envelope);
+            }
+        }
+
+        let mut replies = vec![];
+        for envelope in arrived {
+            let (src, dst_addr) = (envelope.src, envelope.dst);
+            let (protocol, len) = (envelope.message.name(), envelope.message.len());
+
+            if dst.is_rx_queue_full(&envelope.message) {
+                tracing::warn!(target: TRACING_TARGET, ?dst_addr, ?src, protocol = %envelope.message, "Dropped (Receive queue full)");
+                self.log.record(src, dst_addr, protocol, len, false);
+                continue;
+            }
+
+            self.log.record(src, dst_addr, protocol, len, true);
+
+            if let Err(message) = dst.receive_from_network(envelope) {
+                replies.push((dst_addr, src, message));
+            }
+        }
+
+        // Responses to failed deliveries are best effort
+        for (src, dst, message) in replies {
+            let _ = self.enqueue_message(rand, src, dst, Duration::ZERO, message);
+        }
+    }
+
+    /// The number of messages sent but not yet received, including those on
+    /// hold or queued behind a receive limit.
+    pub(crate) fn in_flight_messages(&self) -> usize {
+        let links: usize = self
+            .links
+            .values()
+            .map(|link| {
+                link.sent.len() + link.deliverable.values().map(VecDeque::len).sum::<usize>()
+            })
+            .sum();
+        let limited: usize = self.rx_limits.values().map(|limit| limit.queue.len()).sum();
+
+        links + limited
+    }
+
+    /// The number of bytes of TCP data sent from `src` to `dst` that have not
+    /// yet been received.
+    pub(crate) fn tcp_bytes_in_flight(&self, src: SocketAddr, dst: SocketAddr) -> usize {
+        let is_match = |s: SocketAddr, d: SocketAddr, message: &Protocol| {
+            s == src && d == dst && matches!(message, Protocol::Tcp(_))
+        };
+
+        let mut bytes = 0;
+        for link in self.links.values() {
+            for sent in &link.sent {
+                if is_match(sent.src, sent.dst, &sent.protocol) {
+                    bytes += sent.protocol.len();
+                }
+            }
+
+            for envelope in link.deliverable.values().flatten() {
+                if is_match(envelope.src, envelope.dst, &envelope.message) {
+                    bytes += envelope.message.len();
+                }
+            }
+        }
+
+        for (_, envelope) in self.rx_limits.values().flat_map(|limit| &limit.queue) {
+            if is_match(envelope.src, envelope.dst, &envelope.message) {
+                bytes += envelope.message.len();
             }
         }
+
+        bytes
+    }
+
+    /// Wake `waker` on the next network tick, to check again whether
+    /// messages in flight have been delivered.
+    pub(crate) fn wake_on_tick(&mut self, waker: &Waker) {
+        self.flush_waiters.push(waker.clone());
+    }
+
+    /// Discard all messages in flight to `addr`, including held messages and
+    /// those queued by a receive limit. Returns the number of discarded
+    /// messages.
+    pub(crate) fn drain_messages(&mut self, addr: IpAddr) -> usize {
+        let mut drained = vec![];
+        for (pair, link) in &mut self.links {
+            if pair.0 == addr || pair.1 == addr {
+                drained.extend(link.drain(addr));
+            }
+        }
+
+        if let Some(limit) = self.rx_limits.get_mut(&addr) {
+            drained.extend(limit.queue.drain(..).map(|(_, envelope)| envelope));
+        }
+
+        for envelope in &drained {
+            let Envelope { src, dst, message } = envelope;
+
+            tracing::trace!(target: TRACING_TARGET, ?src, ?dst, protocol = %message, "Drop (Drained)");
+            self.log
+                .record(*src, *dst, message.name(), message.len(), false);
+        }
+
+        drained.len()
+    }
+
+    /// Reverse the delivery order of all messages in flight from `src` to
+    /// `dst`. Returns the number of reordered messages.
+    pub(crate) fn reorder_queue(&mut self, src: IpAddr, dst: IpAddr) -> usize {
+        let interfaces = &self.interfaces;
+        let resolve = |addr: IpAddr| interfaces.get(&addr).copied().unwrap_or(addr);
+
+        let mut reordered = 0;
+        for link in self.links.values_mut() {
+            reordered +=
+                link.reverse(|sent| resolve(sent.src.ip()) == src && resolve(sent.dst.ip()) == dst);
+        }
+
+        tracing::trace!(target: TRACING_TARGET, ?src, ?dst, reordered, "Reorder");
+
+        reordered
     }
 
     pub(crate) fn hold(&mut self, a: IpAddr, b: IpAddr) {
@@ -240,29 +920,497 @@ impl Topology {
 
     pub(crate) fn partition(&mut self, a: IpAddr, b: IpAddr) {
         self.links[&Pair::new(a, b)].explicit_partition();
+        self.log.event(TimelineEventKind::PartitionApplied { a, b });
     }
 
     pub(crate) fn repair(&mut self, a: IpAddr, b: IpAddr) {
         self.links[&Pair::new(a, b)].explicit_repair();
+        self.log
+            .event(TimelineEventKind::PartitionRepaired { a, b });
+        self.repair_one_way(a, b);
+        self.repair_one_way(b, a);
+    }
+
+    /// Drop messages from `src` to `dst`, but not from `dst` to `src`.
+    pub(crate) fn partition_one_way(&mut self, src: IpAddr, dst: IpAddr) {
+        self.one_way_partitions.insert((src, dst));
     }
 
-    pub(crate) fn tick_by(&mut self, duration: Duration) {
+    pub(crate) fn repair_one_way(&mut self, src: IpAddr, dst: IpAddr) {
+        self.one_way_partitions.shift_remove(&(src, dst));
+    }
+
+    pub(crate) fn is_partitioned(&self, a: IpAddr, b: IpAddr) -> bool {
+        matches!(
+            self.links[&Pair::new(a, b)].state,
+            State::ExplicitPartition | State::RandPartition
+        )
+    }
+
+    /// Whether messages from `src` can currently reach `dst`, following the
+    /// same path through NATs and routers as sent messages.
+    pub(crate) fn is_reachable(&self, src: IpAddr, dst: IpAddr) -> bool {
+        let (src, dst) = (self.resolve(src), self.resolve(dst));
+
+        if self.one_way_partitions.contains(&(src, dst)) {
+            return false;
+        }
+
+        let mut from = src;
+        let mut to = self.first_hop(src, dst).unwrap_or(dst);
+
+        // Bounded in case routes form a loop.
+        for _ in 0..=self.routers.len() + self.nats.len() {
+            if from == to {
+                return false;
+            }
+
+            match self.links.get(&Pair::new(from, to)) {
+                Some(link)
+                    if !matches!(link.state, State::ExplicitPartition | State::RandPartition) => {}
+                _ => return false,
+            }
+
+            if to == dst {
+                return true;
+            }
+
+            // Routers forward to their next hop, and NATs to the destination.
+            let next = match self.routers.get(&to) {
+                Some(routes) => match longest_match(routes, dst) {
+                    Some(route) if self.routers.contains_key(&route.next_hop) => route.next_hop,
+                    Some(_) => dst,
+                    None => return false,
+                },
+                None => dst,
+            };
+
+            from = to;
+            to = next;
+        }
+
+        false
+    }
+
+    pub(crate) fn tick_by(&mut self, rand: &mut dyn RngCore, duration: Duration) {
         let _ = self.rt.tick(duration);
+        self.log.tick += 1;
+        self.log.elapsed += duration;
+
         for link in self.links.values_mut() {
             link.tick(self.rt.now());
         }
+
+        self.forward_messages(rand);
+        self.translate_messages(rand);
+
+        for waker in self.flush_waiters.drain(..) {
+            waker.wake();
+        }
     }
 
-    pub(crate) fn iter_mut(&mut self) -> LinksIter {
+    /// Render the topology as a Graphviz DOT graph, with a node for each of
+    /// `hosts` labeled by `name`, and an edge for each link.
+    pub(crate) fn dot(
+        &self,
+        hosts: impl Iterator<Item = IpAddr>,
+        name: impl Fn(IpAddr) -> Option<String>,
+    ) -> String {
+        let mut dot = String::from("graph turmoil {\n");
+
+        for addr in hosts {
+            let shape = if self.routers.contains_key(&addr) {
+                "box"
+            } else if self.nats.contains_key(&addr) {
+                "diamond"
+            } else {
+                "ellipse"
+            };
+
+            let label = match name(addr) {
+                Some(name) => format!("{name}\\n{addr}"),
+                None => addr.to_string(),
+            };
+
+            let _ = writeln!(dot, "    \"{addr}\" [label=\"{label}\", shape={shape}];");
+        }
+
+        for (pair, link) in &self.links {
+            let latency = link
+                .config
+                .latency
+                .as_ref()
+                .unwrap_or(self.config.latency());
+            let loss = link
+                .config
+                .message_loss
+                .as_ref()
+                .unwrap_or(self.config.message_loss());
+
+            let (state, style) = match link.state {
+                State::Healthy => ("healthy", "solid"),
+                State::ExplicitPartition => ("partitioned", "dashed"),
+                State::RandPartition => ("partitioned (random)", "dashed"),
+                State::Hold => ("held", "dotted"),
+            };
+
+            let _ = writeln!(
+                dot,
+                "    \"{}\" -- \"{}\" [label=\"latency={:?}..{:?}\\nloss={}\\n{}\", style={}];",
+                pair.0,
+                pair.1,
+                latency.min_message_latency,
+                latency.max_message_latency,
+                loss.fail_rate,
+                state,
+                style,
+            );
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+
+    pub(crate) fn iter_mut(&mut self) -> LinksIter<'_> {
         LinksIter {
             iter: self.links.iter_mut(),
         }
     }
 }
 
+/// A message that has been delivered to, or dropped on the way to, its
+/// destination. See [`Sim::message_log`](crate::Sim::message_log).
+#[derive(Debug, Clone)]
+pub struct MessageRecord {
+    /// The simulation step during which the message was delivered or dropped.
+    pub tick: u64,
+
+    /// The simulated time at which the message was delivered or dropped.
+    pub simtime: Duration,
+
+    /// Where the message was sent from.
+    pub src: SocketAddr,
+
+    /// Where the message was sent to.
+    pub dst: SocketAddr,
+
+    /// The kind of message, e.g. "UDP" or "TCP SYN".
+    pub protocol: &'static str,
+
+    /// The number of payload bytes carried by the message.
+    pub len: usize,
+
+    /// Whether the message reached its destination host.
+    pub delivered: bool,
+}
+
+/// Messages recorded by a [`LinkRecorder`], shared with the topology.
+type Packets = RefCell<Vec<CapturedPacket>>;
+
+/// Records the messages sent over a link, see
+/// [`Sim::add_transparent_link_recorder`](crate::Sim::add_transparent_link_recorder).
+///
+/// Recording stops once the recorder is dropped.
+#[derive(Debug)]
+pub struct LinkRecorder {
+    packets: Rc<Packets>,
+}
+
+impl LinkRecorder {
+    /// Take all messages recorded since the last drain, in the order they
+    /// were sent.
+    pub fn drain(&self) -> Vec<CapturedPacket> {
+        mem::take(&mut self.packets.borrow_mut())
+    }
+}
+
+/// A message recorded by a [`LinkRecorder`].
+#[derive(Debug, Clone)]
+pub struct CapturedPacket {
+    /// The simulation step during which the message was sent.
+    pub tick: u64,
+
+    /// The simulated time at which the message was sent.
+    pub simtime: Duration,
+
+    /// Where the message was sent from.
+    pub src: SocketAddr,
+
+    /// Where the message was sent to.
+    pub dst: SocketAddr,
+
+    /// The kind of message, e.g. "UDP" or "TCP SYN".
+    pub protocol: &'static str,
+
+    /// The payload carried by the message, empty for TCP control segments.
+    pub payload: Bytes,
+}
+
+/// An event in the [timeline](crate::Sim::timeline) of a simulation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimelineEvent {
+    /// The simulation step during which the event happened.
+    pub tick: u64,
+
+    /// The simulated time at which the event happened.
+    pub simtime: Duration,
+
+    /// The simulated wall clock time at which the event happened, which is
+    /// `simtime` after the simulation's epoch.
+    pub wall_time: SystemTime,
+
+    /// What happened.
+    pub kind: TimelineEventKind,
+}
+
+/// The kinds of [`TimelineEvent`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TimelineEventKind {
+    /// A message was sent onto the network.
+    MessageSent {
+        src: SocketAddr,
+        dst: SocketAddr,
+        protocol: &'static str,
+        len: usize,
+    },
+
+    /// A message reached its destination host.
+    MessageDelivered {
+        src: SocketAddr,
+        dst: SocketAddr,
+        protocol: &'static str,
+        len: usize,
+    },
+
+    /// A message was dropped on the way to its destination.
+    MessageDropped {
+        src: SocketAddr,
+        dst: SocketAddr,
+        protocol: &'static str,
+        len: usize,
+    },
+
+    /// A TCP connection from `local` to `remote` was established.
+    ConnectionOpened {
+        local: SocketAddr,
+        remote: SocketAddr,
+    },
+
+    /// The link between two hosts was partitioned.
+    PartitionApplied { a: IpAddr, b: IpAddr },
+
+    /// The link between two hosts was repaired.
+    PartitionRepaired { a: IpAddr, b: IpAddr },
+
+    /// A host's software was crashed.
+    HostCrashed { addr: IpAddr },
+}
+
+/// Ordered history of delivered and dropped messages, and of the events on
+/// the simulation's timeline.
+#[derive(Default)]
+pub(crate) struct MessageLog {
+    tick: u64,
+    elapsed: Duration,
+    /// Whether records and timeline events are kept, see
+    /// [`Builder::record_messages`](crate::Builder::record_messages).
+    pub(crate) enabled: bool,
+    /// The number of messages delivered and dropped, counted whether or not
+    /// they are recorded.
+    pub(crate) delivered: u64,
+    pub(crate) dropped: u64,
+    pub(crate) records: Vec<MessageRecord>,
+    /// Events with the tick and simulated time they happened at.
+    pub(crate) timeline: Vec<(u64, Duration, TimelineEventKind)>,
+}
+
+impl MessageLog {
+    fn record(
+        &mut self,
+        src: SocketAddr,
+        dst: SocketAddr,
+        protocol: &'static str,
+        len: usize,
+        delivered: bool,
+    ) {
+        if delivered {
+            self.delivered += 1;
+        } else {
+            self.dropped += 1;
+        }
+
+        if self.enabled {
+            self.records.push(MessageRecord {
+                tick: self.tick,
+                simtime: self.elapsed,
+                src,
+                dst,
+                protocol,
+                len,
+                delivered,
+            });
+        }
+
+        self.event(if delivered {
+            TimelineEventKind::MessageDelivered {
+                src,
+                dst,
+                protocol,
+                len,
+            }
+        } else {
+            TimelineEventKind::MessageDropped {
+                src,
+                dst,
+                protocol,
+                len,
+            }
+        });
+    }
+
+    /// Append an event to the timeline at the current tick.
+    pub(crate) fn event(&mut self, kind: TimelineEventKind) {
+        if self.enabled {
+            self.timeline.push((self.tick, self.elapsed, kind));
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.records.clear();
+        self.timeline.clear();
+    }
+
+    fn sent(&mut self, src: SocketAddr, dst: SocketAddr, message: &Protocol) {
+        self.event(TimelineEventKind::MessageSent {
+            src,
+            dst,
+            protocol: message.name(),
+            len: message.len(),
+        });
+    }
+}
+
+/// Network address translation state.
+struct Nat {
+    /// Hosts in this network have their traffic translated.
+    internal: IpNet,
+
+    /// Maps internal addresses to external ports.
+    table: IndexMap<SocketAddr, u16>,
+
+    /// Maps external ports back to internal addresses.
+    ports: IndexMap<u16, SocketAddr>,
+
+    /// The next external port to allocate.
+    next_port: u16,
+}
+
+/// The first external port a NAT allocates. Ports are allocated in turn up to
+/// 65535, then from here again.
+const NAT_FIRST_PORT: u16 = 1024;
+
+impl Nat {
+    fn new(internal: IpNet) -> Nat {
+        Nat {
+            internal,
+            table: IndexMap::new(),
+            ports: IndexMap::new(),
+            next_port: NAT_FIRST_PORT,
+        }
+    }
+
+    /// The external port for `internal`, allocating one if necessary. Ports
+    /// still mapped are skipped, and an error is returned if none are free.
+    fn map(&mut self, internal: SocketAddr) -> io::Result<u16> {
+        if let Some(port) = self.table.get(&internal) {
+            return Ok(*port);
+        }
+
+        if self.ports.len() > (u16::MAX - NAT_FIRST_PORT) as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrNotAvailable,
+                "no free NAT ports",
+            ));
+        }
+
+        let mut port = self.next_port;
+        while self.ports.contains_key(&port) {
+            port = if port == u16::MAX {
+                NAT_FIRST_PORT
+            } else {
+                port + 1
+            };
+        }
+        self.next_port = if port == u16::MAX {
+            NAT_FIRST_PORT
+        } else {
+            port + 1
+        };
+
+        self.table.insert(internal, port);
+        self.ports.insert(port, internal);
+        Ok(port)
+    }
+
+    fn reverse(&self, port: u16) -> Option<SocketAddr> {
+        self.ports.get(&port).copied()
+    }
+}
+
+/// Receive throughput limit for a host.
+///
+/// Messages are received one after the other, each taking time proportional
+/// to its size. Messages arriving while the host is busy receiving wait in
+/// `queue`.
+struct RxLimit {
+    bytes_per_sec: u64,
+
+    /// When the host is done receiving all queued messages.
+    next: Instant,
+
+    /// Messages paired with when they have been fully received.
+    queue: VecDeque<(Instant, Envelope)>,
+}
+
+impl RxLimit {
+    fn enqueue(&mut self, now: Instant, envelope: Envelope) {
+        let len = envelope.message.len() as f64;
+        let duration = Duration::from_secs_f64(len / self.bytes_per_sec as f64);
+
+        self.next = self.next.max(now) + duration;
+        self.queue.push_back((self.next, envelope));
+    }
+
+    fn dequeue(&mut self, now: Instant) -> Option<Envelope> {
+        match self.queue.front() {
+            Some((at, _)) if *at <= now => self.queue.pop_front().map(|(_, e)| e),
+            _ => None,
+        }
+    }
+}
+
+// Whether an event with probability `p` happens. The rng is only consulted
+// when `p` is non-zero, keeping seeded simulations that do not use the fault
+// reproducible.
+fn chance(rand: &mut dyn RngCore, p: f64) -> bool {
+    p > 0.0 && rand.gen_bool(p)
+}
+
+/// Pick the most specific route matching `dst`.
+fn longest_match(routes: &[Route], dst: IpAddr) -> Option<&Route> {
+    routes
+        .iter()
+        .filter(|r| r.prefix.contains(dst))
+        .max_by_key(|r| r.prefix.prefix_len())
+}
+
 struct Sent {
     src: SocketAddr,
     dst: SocketAddr,
+    /// The end of the link receiving the message. This is `dst`, unless the
+    /// message is being routed.
+    hop: IpAddr,
     status: DeliveryStatus,
     protocol: Protocol,
 }
@@ -286,22 +1434,50 @@ impl Link {
             sent: VecDeque::new(),
             deliverable: IndexMap::new(),
             now,
+            busy_until: IndexMap::new(),
+            priorities: IndexMap::new(),
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn enqueue_message(
         &mut self,
         global_config: &config::Link,
         rand: &mut dyn RngCore,
         src: SocketAddr,
         dst: SocketAddr,
+        hop: IpAddr,
+        latency: Duration,
         message: Protocol,
-    ) {
+    ) -> bool {
         tracing::trace!(target: TRACING_TARGET, ?src, ?dst, protocol = %message, "Send");
 
         self.rand_partition_or_repair(global_config, rand);
-        self.enqueue(global_config, rand, src, dst, message);
+        let is_queued = self.enqueue(global_config, rand, src, dst, hop, latency, message);
+        self.process_deliverables();
+
+        is_queued
+    }
+
+    // src -> router -> link -> hop
+    //                  ^-- you are here!
+    fn forward(
+        &mut self,
+        global_config: &config::Link,
+        rand: &mut dyn RngCore,
+        envelope: Envelope,
+        hop: IpAddr,
+        latency: Duration,
+    ) -> bool {
+        let Envelope { src, dst, message } = envelope;
+
+        tracing::trace!(target: TRACING_TARGET, ?src, ?dst, ?hop, protocol = %message, "Forward");
+
+        self.rand_partition_or_repair(global_config, rand);
+        let is_queued = self.enqueue(global_config, rand, src, dst, hop, latency, message);
         self.process_deliverables();
+
+        is_queued
     }
 
     // src -> link -> dst
@@ -309,18 +1485,27 @@ impl Link {
     //
     // Messages may be dropped, sit on the link for a while (due to latency, or
     // because the link has stalled), or be delivered immediately.
+    //
+    // `latency` is added on top of the link's latency, which is used by routers
+    // to delay forwarded messages.
+    //
+    // Returns false if the message was dropped.
+    #[allow(clippy::too_many_arguments)]
     fn enqueue(
         &mut self,
         global_config: &config::Link,
         rand: &mut dyn RngCore,
         src: SocketAddr,
         dst: SocketAddr,
-        message: Protocol,
-    ) {
+        hop: IpAddr,
+        latency: Duration,
+        mut message: Protocol,
+    ) -> bool {
         let status = match self.state {
             State::Healthy => {
-                let delay = self.delay(global_config.latency(), rand);
-                DeliveryStatus::DeliverAfter(self.now + delay)
+                let sent_at = self.transmit(hop, message.len());
+                let delay = self.delay(global_config.latency(), hop, rand);
+                DeliveryStatus::DeliverAfter(sent_at + delay + latency)
             }
             State::Hold => {
                 tracing::trace!(target: TRACING_TARGET,?src, ?dst, protocol = %message, "Hold");
@@ -330,18 +1515,85 @@ impl Link {
             _ => {
                 tracing::trace!(target: TRACING_TARGET,?src, ?dst, protocol = %message, "Drop");
 
-                return;
+                return false;
             }
         };
 
+        if let Some(corruption) = self.config.corruption.get(&hop) {
+            if chance(rand, corruption.probability) {
+                let count = message.corrupt(rand, corruption.max_bytes);
+                tracing::trace!(target: TRACING_TARGET, ?src, ?dst, bytes = count, "Corrupt");
+            }
+        }
+
+        let duplicate = match &message {
+            Protocol::Udp(Datagram(bytes, tos)) if chance(rand, self.config.duplication_rate) => {
+                Some(Datagram(bytes.clone(), *tos))
+            }
+            _ => None,
+        };
+
         let sent = Sent {
             src,
             dst,
+            hop,
             status,
             protocol: message,
         };
 
         self.sent.push_back(sent);
+
+        if let Some(datagram) = duplicate {
+            tracing::trace!(target: TRACING_TARGET, ?src, ?dst, "Duplicate");
+
+            let status = match self.state {
+                State::Hold => DeliveryStatus::Hold,
+                _ => {
+                    let sent_at = self.transmit(hop, datagram.0.len());
+                    let delay = self.delay(global_config.latency(), hop, rand);
+                    DeliveryStatus::DeliverAfter(sent_at + delay + latency)
+                }
+            };
+
+            self.sent.push_back(Sent {
+                src,
+                dst,
+                hop,
+                status,
+                protocol: Protocol::Udp(datagram),
+            });
+        }
+
+        if chance(rand, self.config.reorder_rate) {
+            let last = self.sent.len() - 1;
+            let previous = self.sent.range(..last).rposition(|sent| sent.hop == hop);
+
+            if let Some(previous) = previous {
+                tracing::trace!(target: TRACING_TARGET, ?src, ?dst, "Reorder");
+
+                self.swap_order(previous, last);
+            }
+        }
+
+        true
+    }
+
+    // When a message of `len` bytes sent toward `to` now has been fully
+    // transmitted, waiting for messages sent before it on a link with limited
+    // bandwidth.
+    fn transmit(&mut self, to: IpAddr, len: usize) -> Instant {
+        let Some(bandwidth) = self.config.bandwidth else {
+            return self.now;
+        };
+
+        let start = match self.busy_until.get(&to) {
+            Some(&busy_until) if busy_until > self.now => busy_until,
+            _ => self.now,
+        };
+        let done = start + Duration::from_secs_f64(len as f64 / bandwidth as f64);
+        self.busy_until.insert(to, done);
+
+        done
     }
 
     fn tick(&mut self, now: Instant) {
@@ -364,57 +1616,97 @@ impl Link {
                         dst: sent.dst,
                         message: sent.protocol,
                     };
-                    self.deliverable
-                        .entry(sent.dst.ip())
-                        .or_default()
-                        .push_back(envelope);
+                    let queue = self.deliverable.entry(sent.hop).or_default();
+
+                    match self.priorities.get(&sent.hop) {
+                        // Behind messages of the same or a higher priority,
+                        // keeping their order.
+                        Some(priority) => {
+                            let p = priority(&envelope.message);
+                            let at = queue
+                                .iter()
+                                .position(|e| priority(&e.message) > p)
+                                .unwrap_or(queue.len());
+                            queue.insert(at, envelope);
+                        }
+                        None => queue.push_back(envelope),
+                    }
                     deliverable += 1;
                 }
             }
         }
     }
 
-    // FIXME: This implementation does not respect message delivery order. If
-    // host A and host B are ordered (by addr), and B sends before A, then this
-    // method will deliver A's message before B's.
-    fn deliver_messages(
-        &mut self,
-        global_config: &config::Link,
-        rand: &mut dyn RngCore,
-        host: &mut Host,
-    ) {
-        let deliverable = self
-            .deliverable
-            .entry(host.addr)
+    fn take_deliverable(&mut self, addr: IpAddr) -> Vec<Envelope> {
+        self.deliverable
+            .entry(addr)
             .or_default()
             .drain(..)
-            .collect::<Vec<Envelope>>();
+            .collect()
+    }
 
-        for message in deliverable {
-            let (src, dst) = (message.src, message.dst);
-            if let Err(message) = host.receive_from_network(message) {
-                self.enqueue_message(global_config, rand, dst, src, message);
-            }
-        }
+    /// Remove all messages on this link bound for `addr`, whether they are
+    /// deliverable, scheduled or on hold.
+    fn drain(&mut self, addr: IpAddr) -> Vec<Envelope> {
+        let mut drained = self.take_deliverable(addr);
+
+        let (sent, keep) = self.sent.drain(..).partition(|sent| sent.hop == addr);
+        self.sent = keep;
+
+        drained.extend(sent.into_iter().map(|sent: Sent| Envelope {
+            src: sent.src,
+            dst: sent.dst,
+            message: sent.protocol,
+        }));
+
+        drained
     }
 
     // Randomly break or repair this link.
     fn rand_partition_or_repair(&mut self, global_config: &config::Link, rand: &mut dyn RngCore) {
         match self.state {
-            State::Healthy => {
-                if self.rand_partition(global_config.message_loss(), rand) {
-                    self.state = State::RandPartition;
-                }
+            State::Healthy if self.rand_partition(global_config.message_loss(), rand) => {
+                self.state = State::RandPartition;
             }
-            State::RandPartition => {
-                if self.rand_repair(global_config.message_loss(), rand) {
-                    self.release();
-                }
+            State::RandPartition if self.rand_repair(global_config.message_loss(), rand) => {
+                self.release();
             }
             _ => {}
         }
     }
 
+    // Reverse the delivery order of the sent messages matching `is_match`.
+    //
+    // Each message takes over the delivery status of its mirror in delivery
+    // order, so the first message to arrive is the last that would have. The
+    // entries are swapped as well, as messages that become deliverable on the
+    // same tick are delivered in the order they sit in `sent`.
+    fn reverse(&mut self, is_match: impl Fn(&Sent) -> bool) -> usize {
+        let mut slots = (0..self.sent.len())
+            .filter(|&i| is_match(&self.sent[i]))
+            .collect::<Vec<_>>();
+
+        slots.sort_by_key(|&i| match self.sent[i].status {
+            DeliveryStatus::DeliverAfter(time) => (false, Some(time)),
+            DeliveryStatus::Hold => (true, None),
+        });
+
+        for k in 0..slots.len() / 2 {
+            self.swap_order(slots[k], slots[slots.len() - 1 - k]);
+        }
+
+        slots.len()
+    }
+
+    // Swap the sent messages at `a` and `b`, leaving the delivery statuses in
+    // place.
+    fn swap_order(&mut self, a: usize, b: usize) {
+        let a_status = mem::replace(&mut self.sent[a].status, DeliveryStatus::Hold);
+        let b_status = mem::replace(&mut self.sent[b].status, a_status);
+        self.sent[a].status = b_status;
+        self.sent.swap(a, b);
+    }
+
     fn hold(&mut self) {
         self.state = State::Hold;
     }
@@ -451,7 +1743,12 @@ impl Link {
         repair_rate > 0.0 && rand.gen_bool(repair_rate)
     }
 
-    fn delay(&self, global: &config::Latency, rand: &mut dyn RngCore) -> Duration {
+    // `to` is the end of the link receiving the message.
+    fn delay(&self, global: &config::Latency, to: IpAddr, rand: &mut dyn RngCore) -> Duration {
+        if let Some(delay) = self.config.directional_latency.get(&to) {
+            return *delay;
+        }
+
         let config = self.config.latency.as_ref().unwrap_or(global);
 
         let mult = config.latency_distribution.sample(rand);
@@ -471,3 +1768,48 @@ impl Link {
             .get_or_insert_with(|| global.clone())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{Nat, NAT_FIRST_PORT};
+    use std::io;
+    use std::net::{Ipv4Addr, SocketAddr};
+
+    fn internal(i: u32) -> SocketAddr {
+        let [_, a, b, c] = i.to_be_bytes();
+        (Ipv4Addr::new(10, a, b, c), 1234).into()
+    }
+
+    #[test]
+    fn nat_skips_mapped_ports() -> io::Result<()> {
+        let mut nat = Nat::new("10.0.0.0/8".parse().unwrap());
+        assert_eq!(NAT_FIRST_PORT, nat.map(internal(0))?);
+
+        nat.next_port = u16::MAX;
+        assert_eq!(u16::MAX, nat.map(internal(1))?);
+
+        // wraps around, past the port mapped first
+        assert_eq!(NAT_FIRST_PORT + 1, nat.map(internal(2))?);
+        assert_eq!(Some(internal(1)), nat.reverse(u16::MAX));
+
+        Ok(())
+    }
+
+    #[test]
+    fn nat_ports_exhausted() -> io::Result<()> {
+        let mut nat = Nat::new("10.0.0.0/8".parse().unwrap());
+        let free = (u16::MAX - NAT_FIRST_PORT) as u32 + 1;
+
+        for i in 0..free {
+            nat.map(internal(i))?;
+        }
+
+        let err = nat.map(internal(free)).unwrap_err();
+        assert_eq!(io::ErrorKind::AddrNotAvailable, err.kind());
+
+        // existing mappings still resolve
+        assert_eq!(NAT_FIRST_PORT, nat.map(internal(0))?);
+
+        Ok(())
+    }
+}
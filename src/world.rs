@@ -1,12 +1,15 @@
-use crate::envelope::Protocol;
-use crate::{config, Dns, Host, ToIpAddr, ToIpAddrs, Topology, TRACING_TARGET};
+use crate::envelope::{Datagram, Envelope, Protocol};
+use crate::net::SocketPair;
+use crate::{config, Dns, Host, IpNet, Route, ToIpAddr, ToIpAddrs, Topology, TRACING_TARGET};
 
 use indexmap::IndexMap;
 use rand::RngCore;
 use scoped_tls::scoped_thread_local;
 use std::cell::RefCell;
+use std::io;
 use std::net::{IpAddr, SocketAddr};
 use std::time::Duration;
+use tokio::time::Instant;
 
 /// Tracks all the state for the simulated world.
 pub(crate) struct World {
@@ -25,19 +28,108 @@ pub(crate) struct World {
     /// Random number generator used for all decisions. To make execution
     /// determinstic, reuse the same seed.
     pub(crate) rng: Box<dyn RngCore>,
+
+    /// If set, limits the rate of messages across all hosts.
+    pub(crate) rate_limit: Option<TokenBucket>,
+
+    /// If set, message payloads are truncated to this many bytes.
+    pub(crate) max_message_size: Option<usize>,
+
+    /// If set, the send buffer size in bytes of TCP streams created from now
+    /// on, see [`Sim::set_default_tcp_buffer_size`](crate::Sim::set_default_tcp_buffer_size).
+    pub(crate) tcp_buffer_size: Option<usize>,
+
+    /// Faults scheduled for a window of simulated time.
+    fault_windows: Vec<FaultWindow>,
+
+    /// Loss windows in effect on each link, by `(src, dst)`.
+    loss_spikes: IndexMap<(IpAddr, IpAddr), LossSpikes>,
+}
+
+/// A fault between `a` and `b` that is in effect from `start` until `end` of
+/// simulated time.
+struct FaultWindow {
+    a: IpAddr,
+    b: IpAddr,
+    start: Duration,
+    end: Duration,
+    fault: WindowFault,
+    is_active: bool,
+}
+
+enum WindowFault {
+    /// The link between `a` and `b` is partitioned.
+    Partition,
+
+    /// Messages from `a` to `b` are lost with `probability`.
+    Loss { probability: f64 },
+}
+
+/// Overlapping loss windows on a link. The most recently started is in
+/// effect, and once all have ended the loss from before the first is
+/// restored.
+struct LossSpikes {
+    /// The loss before the first window started.
+    base: Option<f64>,
+
+    /// The end and probability of each window in effect, in the order they
+    /// started.
+    active: Vec<(Duration, f64)>,
+}
+
+/// A token bucket, refilled at `rate` tokens per second and holding at most a
+/// single token.
+///
+/// Each message takes a token. When none are available the message is delayed
+/// until its token would have been refilled, so messages exceeding the rate
+/// queue up behind one another.
+pub(crate) struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    pub(crate) fn new(rate: f64, now: Instant) -> TokenBucket {
+        assert!(rate > 0.0, "rate must be positive");
+
+        TokenBucket {
+            rate,
+            tokens: 1.0,
+            last: now,
+        }
+    }
+
+    /// Take a token, returning how long the message must be delayed.
+    fn take(&mut self, now: Instant) -> Duration {
+        let refill = now.saturating_duration_since(self.last).as_secs_f64() * self.rate;
+        self.tokens = (self.tokens + refill).min(1.0) - 1.0;
+        self.last = now;
+
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate)
+        }
+    }
 }
 
 scoped_thread_local!(static CURRENT: RefCell<World>);
 
 impl World {
     /// Initialize a new world.
-    pub(crate) fn new(link: config::Link, rng: Box<dyn RngCore>) -> World {
+    pub(crate) fn new(link: config::Link, dns: config::Dns, rng: Box<dyn RngCore>) -> World {
         World {
             hosts: IndexMap::new(),
             topology: Topology::new(link),
-            dns: Dns::new(),
+            dns: Dns::new().config(dns),
             current: None,
             rng,
+            rate_limit: None,
+            max_message_size: None,
+            tcp_buffer_size: None,
+            fault_windows: vec![],
+            loss_spikes: IndexMap::new(),
         }
     }
 
@@ -68,6 +160,21 @@ impl World {
         self.hosts.get_mut(&addr).expect("host missing")
     }
 
+    /// The addresses to race when connecting to `dst`, IPv6 first, if both
+    /// the current host and the host at `dst` are dual-stack.
+    pub(crate) fn happy_eyeballs_addrs(&self, dst: SocketAddr) -> Option<(SocketAddr, SocketAddr)> {
+        let current = self.current.expect("current host missing");
+        self.hosts[&current].dual_stack_addrs()?;
+
+        let (v6, v4) = self
+            .hosts
+            .values()
+            .find(|host| host.has_addr(dst.ip()))?
+            .dual_stack_addrs()?;
+
+        Some(((v6, dst.port()).into(), (v4, dst.port()).into()))
+    }
+
     pub(crate) fn lookup(&mut self, host: impl ToIpAddr) -> IpAddr {
         self.dns.lookup(host)
     }
@@ -76,10 +183,156 @@ impl World {
         self.dns.lookup_many(hosts)
     }
 
+    /// Returns the current host's cached resolution of `name`, if it has not
+    /// expired. A cached `None` is a negative result.
+    pub(crate) fn resolve_cached(&mut self, name: &str) -> Option<Option<IpAddr>> {
+        let host = self.current_host_mut();
+        let now = host.elapsed();
+        host.dns_cache.get(name, now)
+    }
+
+    /// Resolve `name` without registering it, caching the result on the
+    /// current host for the configured ttl.
+    pub(crate) fn resolve(&mut self, name: &str) -> Option<IpAddr> {
+        let addr = self.dns.resolve(name);
+        let ttl = self.dns.config.ttl;
+
+        if !ttl.is_zero() {
+            let host = self.current_host_mut();
+            let expires = host.elapsed() + ttl;
+            host.dns_cache.insert(name, addr, expires);
+        }
+
+        addr
+    }
+
     pub(crate) fn hold(&mut self, a: IpAddr, b: IpAddr) {
         self.topology.hold(a, b);
     }
 
+    /// Schedule a partition between `a` and `b` from `start` until `end`.
+    pub(crate) fn partition_during(
+        &mut self,
+        a: IpAddr,
+        b: IpAddr,
+        start: Duration,
+        end: Duration,
+    ) {
+        self.schedule_fault(a, b, start, end, WindowFault::Partition);
+    }
+
+    /// Schedule message loss from `src` to `dst` from `start` until `end`.
+    pub(crate) fn loss_during(
+        &mut self,
+        src: IpAddr,
+        dst: IpAddr,
+        start: Duration,
+        end: Duration,
+        probability: f64,
+    ) {
+        self.schedule_fault(src, dst, start, end, WindowFault::Loss { probability });
+    }
+
+    fn schedule_fault(
+        &mut self,
+        a: IpAddr,
+        b: IpAddr,
+        start: Duration,
+        end: Duration,
+        fault: WindowFault,
+    ) {
+        self.fault_windows.push(FaultWindow {
+            a,
+            b,
+            start,
+            end,
+            fault,
+            is_active: false,
+        });
+    }
+
+    /// Apply or undo faults whose scheduled window starts or ends at
+    /// `elapsed` simulated time.
+    pub(crate) fn tick_fault_windows(&mut self, elapsed: Duration) {
+        let mut windows = std::mem::take(&mut self.fault_windows);
+
+        windows.retain_mut(|window| {
+            let (a, b) = (window.a, window.b);
+
+            if !window.is_active && elapsed >= window.start && elapsed < window.end {
+                match &mut window.fault {
+                    WindowFault::Partition => {
+                        tracing::info!(target: TRACING_TARGET, ?a, ?b, "Partition (Scheduled)");
+                        self.partition(a, b);
+                    }
+                    WindowFault::Loss { probability } => {
+                        tracing::info!(target: TRACING_TARGET, src = ?a, dst = ?b, probability, "Loss (Scheduled)");
+                        let base = self.topology.message_loss(a, b);
+                        self.loss_spikes
+                            .entry((a, b))
+                            .or_insert_with(|| LossSpikes {
+                                base,
+                                active: vec![],
+                            })
+                            .active
+                            .push((window.end, *probability));
+                        self.topology.set_message_loss(a, b, Some(*probability));
+                    }
+                }
+
+                window.is_active = true;
+            }
+
+            if elapsed < window.end {
+                return true;
+            }
+
+            if window.is_active {
+                match window.fault {
+                    WindowFault::Partition => {
+                        tracing::info!(target: TRACING_TARGET, ?a, ?b, "Repair (Scheduled)");
+                        self.repair(a, b);
+                    }
+                    WindowFault::Loss { probability } => {
+                        tracing::info!(target: TRACING_TARGET, src = ?a, dst = ?b, "Loss restored (Scheduled)");
+                        self.end_loss_spike(a, b, (window.end, probability));
+                    }
+                }
+            }
+
+            false
+        });
+
+        self.fault_windows = windows;
+    }
+
+    /// End a loss window from `src` to `dst`, falling back to the window that
+    /// started before it, or the loss from before any started. The loss is
+    /// left alone if it was changed while the window was in effect.
+    fn end_loss_spike(&mut self, src: IpAddr, dst: IpAddr, spike: (Duration, f64)) {
+        let Some(spikes) = self.loss_spikes.get_mut(&(src, dst)) else {
+            return;
+        };
+
+        let in_effect = spikes.active.last().map(|&(_, probability)| probability);
+        if let Some(i) = spikes.active.iter().position(|&active| active == spike) {
+            spikes.active.remove(i);
+        }
+
+        let restore = match spikes.active.last() {
+            Some(&(_, probability)) => Some(probability),
+            None => spikes.base,
+        };
+
+        if spikes.active.is_empty() {
+            self.loss_spikes.shift_remove(&(src, dst));
+        }
+
+        if self.topology.message_loss(src, dst) == in_effect {
+            self.topology.set_message_loss(src, dst, restore);
+        }
+    }
+
     pub(crate) fn release(&mut self, a: IpAddr, b: IpAddr) {
         self.topology.release(a, b);
     }
@@ -92,6 +345,14 @@ impl World {
         self.topology.repair(a, b);
     }
 
+    pub(crate) fn partition_one_way(&mut self, src: IpAddr, dst: IpAddr) {
+        self.topology.partition_one_way(src, dst);
+    }
+
+    pub(crate) fn repair_one_way(&mut self, src: IpAddr, dst: IpAddr) {
+        self.topology.repair_one_way(src, dst);
+    }
+
     /// Register a new host with the simulation.
     pub(crate) fn register(&mut self, addr: IpAddr) {
         assert!(
@@ -99,7 +360,7 @@ impl World {
             "already registered host for the given ip address"
         );
 
-        tracing::info!(target: TRACING_TARGET, hostname = ?self.dns.reverse(addr), ?addr, "New");
+        tracing::info!(target: TRACING_TARGET, hostname = ?self.dns.try_reverse(addr), ?addr, "New");
 
         // Register links between the new host and all existing hosts
         for existing in self.hosts.keys() {
@@ -108,13 +369,172 @@ impl World {
 
         // Initialize host state
         self.hosts.insert(addr, Host::new(addr));
+        self.dns.reserve(addr);
+        self.topology.reuse(addr);
+    }
+
+    /// Make the host at `host` reachable at `addr`, in addition to its primary
+    /// address.
+    pub(crate) fn add_host_interface(&mut self, host: IpAddr, addr: IpAddr) {
+        assert!(
+            !self.hosts.contains_key(&addr),
+            "already registered host for the given ip address"
+        );
+
+        tracing::info!(target: TRACING_TARGET, hostname = ?self.dns.try_reverse(host), ?host, ?addr, "Interface");
+
+        self.hosts
+            .get_mut(&host)
+            .expect("missing host")
+            .interfaces
+            .push(addr);
+        self.topology.add_interface(host, addr);
+        self.dns.reserve(addr);
+    }
+
+    /// Remove a host from the simulation.
+    ///
+    /// Links to the host are torn down and its ip address is released for
+    /// reuse. Connections other hosts have to it are reset if
+    /// `reset_connections` is set, and are otherwise left to go quiet.
+    pub(crate) fn deregister(&mut self, addr: IpAddr, reset_connections: bool) {
+        tracing::info!(target: TRACING_TARGET, hostname = ?self.dns.try_reverse(addr), ?addr, "Remove");
+
+        let removed = self.hosts.shift_remove(&addr).expect("missing host");
+        self.topology.deregister(addr);
+
+        if reset_connections {
+            for host in self.hosts.values_mut() {
+                host.tcp.reset_streams(addr);
+
+                for interface in &removed.interfaces {
+                    host.tcp.reset_streams(*interface);
+                }
+            }
+        }
+
+        for interface in &removed.interfaces {
+            self.dns.remove(*interface);
+        }
+        self.dns.remove(addr);
+    }
+
+    /// Reset the TCP connection between `a` and `b` on whichever of the two
+    /// hosts has it open. Returns whether either end was reset.
+    pub(crate) fn kill_connection(&mut self, a: SocketAddr, b: SocketAddr) -> bool {
+        let mut is_reset = false;
+
+        for (local, remote) in [(a, b), (b, a)] {
+            if let Some(host) = self.hosts.values_mut().find(|h| h.has_addr(local.ip())) {
+                is_reset |= host.tcp.reset_stream(SocketPair::new(local, remote));
+            }
+        }
+
+        if is_reset {
+            tracing::info!(target: TRACING_TARGET, ?a, ?b, protocol = %"TCP", "Kill");
+        }
+
+        is_reset
     }
 
     /// Send `message` from `src` to `dst`. Delivery is asynchronous and not
     /// guaranteed.
-    pub(crate) fn send_message(&mut self, src: SocketAddr, dst: SocketAddr, message: Protocol) {
+    ///
+    /// Returns an error if `dst` is neither a host, nor a host that has been
+    /// removed, nor matched by a route.
+    pub(crate) fn send_message(
+        &mut self,
+        src: SocketAddr,
+        dst: SocketAddr,
+        mut message: Protocol,
+    ) -> io::Result<()> {
+        let delay = self.outgoing_delay(&mut message);
+
         self.topology
-            .enqueue_message(&mut self.rng, src, dst, message);
+            .enqueue_message(&mut self.rng, src, dst, delay, message)
+    }
+
+    /// Send a datagram to each host with a socket joined to the multicast
+    /// group `dst`.
+    ///
+    /// Members of the group on the current host, which sends the datagram,
+    /// receive a copy straight away if `multicast_loop` is set, without it
+    /// crossing the network. Datagrams are not forwarded by routers.
+    pub(crate) fn send_multicast(
+        &mut self,
+        src: SocketAddr,
+        dst: SocketAddr,
+        datagram: Datagram,
+        multicast_loop: bool,
+    ) {
+        let current = self.current.expect("current host missing");
+        let members: Vec<_> = self
+            .hosts
+            .iter()
+            .filter(|(_, host)| host.udp.is_multicast_member(dst))
+            .map(|(addr, _)| *addr)
+            .collect();
+
+        for member in members {
+            let mut message = Protocol::Udp(Datagram(datagram.0.clone(), datagram.1));
+
+            if member == current {
+                if multicast_loop {
+                    let host = self.hosts.get_mut(&member).expect("host missing");
+                    let _ = host.receive_from_network(Envelope { src, dst, message });
+                }
+                continue;
+            }
+
+            // Delivery to each member is best effort, as with any datagram
+            let delay = self.outgoing_delay(&mut message);
+            let _ =
+                self.topology
+                    .enqueue_message_to(&mut self.rng, src, dst, member, delay, message);
+        }
+    }
+
+    /// Apply the maximum message size to `message`, returning how long the
+    /// rate limit delays it.
+    fn outgoing_delay(&mut self, message: &mut Protocol) -> Duration {
+        if let Some(max) = self.max_message_size {
+            message.truncate(max);
+        }
+
+        let now = self.topology.now();
+        self.rate_limit
+            .as_mut()
+            .map_or(Duration::ZERO, |bucket| bucket.take(now))
+    }
+
+    /// Send a message with an IP time to live. Each router on the path to
+    /// `dst` decrements the ttl, and the message is dropped once it reaches
+    /// zero.
+    pub(crate) fn send_message_with_ttl(
+        &mut self,
+        src: SocketAddr,
+        dst: SocketAddr,
+        ttl: u32,
+        message: Protocol,
+    ) -> io::Result<()> {
+        if self.topology.router_hops(src.ip(), dst.ip()) >= ttl as usize {
+            self.topology.expire_message(src, dst, message);
+            return Ok(());
+        }
+
+        self.send_message(src, dst, message)
+    }
+
+    /// Register `external` as a NAT for hosts in `internal`.
+    pub(crate) fn add_nat(&mut self, external: IpAddr, internal: IpNet) {
+        self.register(external);
+        self.topology.add_nat(external, internal);
+    }
+
+    /// Register `addr` as a router with the given `routes`.
+    pub(crate) fn add_router(&mut self, addr: IpAddr, routes: Vec<Route>) {
+        self.register(addr);
+        self.topology.add_router(addr, routes);
     }
 
     /// Tick the host at `addr` by `duration`.
@@ -1,18 +1,22 @@
 use std::{
+    cell::Cell,
     io,
-    net::{IpAddr, Ipv4Addr},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
     rc::Rc,
+    sync::Arc,
     time::Duration,
 };
 
+use bytes::{BufMut, Bytes, BytesMut};
 use std::future;
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt, Interest},
     sync::Notify,
     time::timeout,
 };
+use tokio_util::sync::CancellationToken;
 use turmoil::{
-    net::{TcpListener, TcpStream},
+    net::{ConnectOptions, TcpListener, TcpStream},
     Builder, Result,
 };
 
@@ -473,6 +477,52 @@ fn hangup() -> Result {
     sim.run()
 }
 
+#[test]
+fn take_error() -> Result {
+    let notify = Rc::new(Notify::new());
+    let wait = notify.clone();
+
+    let mut sim = Builder::new().build();
+
+    sim.client("server", async move {
+        let listener = bind().await?;
+        let (mut s, _) = listener.accept().await?;
+
+        let mut buf = [0; 8];
+        assert!(matches!(s.read(&mut buf).await, Ok(0)));
+        assert!(s.take_error()?.is_none());
+
+        // The peer is gone, which triggers a RST
+        s.write_u8(1).await?;
+
+        loop {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+
+            if let Some(e) = s.take_error()? {
+                assert_eq!(io::ErrorKind::ConnectionReset, e.kind());
+                break;
+            }
+        }
+
+        // Taking the error clears it
+        assert!(s.take_error()?.is_none());
+        notify.notify_one();
+
+        Ok(())
+    });
+
+    sim.client("client", async move {
+        let s = TcpStream::connect(("server", PORT)).await?;
+
+        drop(s);
+        wait.notified().await;
+
+        Ok(())
+    });
+
+    sim.run()
+}
+
 #[test]
 fn shutdown_write() -> Result {
     let mut sim = Builder::new().build();
@@ -518,6 +568,53 @@ fn shutdown_write() -> Result {
     sim.run()
 }
 
+#[test]
+fn shutdown_split_half_close() -> Result {
+    let mut sim = Builder::new().record_messages(true).build();
+
+    sim.client("server", async move {
+        let listener = bind().await?;
+        let (s, _) = listener.accept().await?;
+        let (mut r, mut w) = s.into_split();
+
+        let mut buf = vec![];
+        r.read_to_end(&mut buf).await?;
+        assert_eq!(b"hello", &buf[..]);
+
+        w.write_all(b"world").await?;
+        w.shutdown().await?;
+
+        Ok(())
+    });
+
+    sim.client("client", async move {
+        let s = TcpStream::connect(("server", PORT)).await?;
+        let (mut r, mut w) = s.into_split();
+
+        w.write_all(b"hello").await?;
+        w.shutdown().await?;
+        drop(w);
+
+        let mut buf = vec![];
+        r.read_to_end(&mut buf).await?;
+        assert_eq!(b"world", &buf[..]);
+
+        Ok(())
+    });
+
+    sim.run()?;
+
+    // dropping a shut down write half doesn't send another FIN
+    let fins = sim
+        .message_log()
+        .iter()
+        .filter(|r| r.protocol == "TCP FIN")
+        .count();
+    assert_eq!(2, fins);
+
+    Ok(())
+}
+
 #[test]
 fn read_with_empty_buffer() -> Result {
     let mut sim = Builder::new().build();
@@ -606,6 +703,75 @@ fn read_buf_smaller_than_msg() -> Result {
     sim.run()
 }
 
+#[test]
+fn read_buf() -> Result {
+    let mut sim = Builder::new().build();
+
+    sim.client("server", async {
+        let listener = bind().await?;
+        let (mut s, _) = listener.accept().await?;
+
+        s.write_all(b"hello").await?;
+        s.write_all(b"world").await?;
+
+        Ok(())
+    });
+
+    sim.client("client", async {
+        let mut s = TcpStream::connect(("server", PORT)).await?;
+
+        let mut buf = BytesMut::with_capacity(8);
+        assert_eq!(5, s.read_buf(&mut buf).await?);
+        assert_eq!(3, s.read_buf(&mut (&mut buf).limit(3)).await?);
+        assert_eq!(&b"hellowor"[..], &buf[..]);
+
+        let mut rest = vec![];
+        assert_eq!(2, s.read_buf(&mut rest).await?);
+        assert_eq!(b"ld", &rest[..]);
+
+        // eof
+        assert_eq!(0, s.read_buf(&mut rest).await?);
+
+        Ok(())
+    });
+
+    sim.run()
+}
+
+#[test]
+fn write_all_buf() -> Result {
+    let mut sim = Builder::new().build();
+
+    sim.client("server", async {
+        let listener = bind().await?;
+        let (mut s, _) = listener.accept().await?;
+
+        let mut buf = [0; 10];
+        s.read_exact(&mut buf).await?;
+        assert_eq!(b"helloworld", &buf);
+
+        Ok(())
+    });
+
+    sim.client("client", async {
+        let mut s = TcpStream::connect(("server", PORT)).await?;
+
+        let mut bytes = Bytes::from_static(b"hello");
+        s.write_all_buf(&mut bytes).await?;
+        assert!(bytes.is_empty());
+
+        s.write_all_buf(&mut Bytes::new()).await?;
+
+        // split into writes that wait for the send buffer to drain
+        s.set_send_buffer_size(3)?;
+        s.write_all_buf(&mut &b"world"[..]).await?;
+
+        Ok(())
+    });
+
+    sim.run()
+}
+
 #[test]
 fn split() -> Result {
     let mut sim = Builder::new().build();
@@ -657,3 +823,1043 @@ fn split() -> Result {
 
     sim.run()
 }
+
+#[test]
+fn try_read_try_write() -> Result {
+    let mut sim = Builder::new().build();
+
+    sim.client("server", async move {
+        let listener = bind().await?;
+        let (s, _) = listener.accept().await?;
+
+        let mut buf = [0; 8];
+        assert_error_kind(s.try_read(&mut buf), io::ErrorKind::WouldBlock);
+
+        loop {
+            match s.try_read(&mut buf) {
+                Ok(n) => {
+                    assert_eq!(b"hello", &buf[..n]);
+                    break;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        assert_eq!(5, s.try_write(b"world")?);
+
+        Ok(())
+    });
+
+    sim.client("client", async move {
+        let mut s = TcpStream::connect(("server", PORT)).await?;
+
+        assert_eq!(5, s.try_write(b"hello")?);
+
+        let mut buf = [0; 5];
+        s.read_exact(&mut buf).await?;
+        assert_eq!(b"world", &buf);
+
+        // EOF once the server hangs up
+        assert_eq!(0, s.read(&mut buf).await?);
+        assert_eq!(0, s.try_read(&mut buf)?);
+
+        Ok(())
+    });
+
+    sim.run()
+}
+
+#[test]
+fn poll_read_ready() -> Result {
+    let mut sim = Builder::new().build();
+
+    sim.client("server", async move {
+        let listener = bind().await?;
+        let (s, _) = listener.accept().await?;
+
+        future::poll_fn(|cx| s.poll_read_ready(cx)).await?;
+
+        let mut buf = [0; 8];
+        let n = s.try_read(&mut buf)?;
+        assert_eq!(b"hello", &buf[..n]);
+
+        // ready once the peer hangs up, and the read yields EOF
+        future::poll_fn(|cx| s.poll_read_ready(cx)).await?;
+        assert_eq!(0, s.try_read(&mut buf)?);
+
+        Ok(())
+    });
+
+    sim.client("client", async move {
+        let s = TcpStream::connect(("server", PORT)).await?;
+
+        future::poll_fn(|cx| s.poll_write_ready(cx)).await?;
+        s.try_write(b"hello")?;
+
+        Ok(())
+    });
+
+    sim.run()
+}
+
+#[test]
+fn readable_writable() -> Result {
+    let mut sim = Builder::new().build();
+
+    sim.client("server", async move {
+        let listener = bind().await?;
+        let (s, _) = listener.accept().await?;
+
+        // not readable until the client writes
+        assert!(timeout(Duration::from_millis(500), s.readable())
+            .await
+            .is_err());
+
+        s.readable().await?;
+
+        let mut buf = [0; 8];
+        let n = s.try_read(&mut buf)?;
+        assert_eq!(b"hello", &buf[..n]);
+
+        s.readable().await?;
+        assert_eq!(0, s.try_read(&mut buf)?);
+
+        Ok(())
+    });
+
+    sim.client("client", async move {
+        let s = TcpStream::connect(("server", PORT)).await?;
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        s.writable().await?;
+        assert_eq!(5, s.try_write(b"hello")?);
+
+        Ok(())
+    });
+
+    sim.run()
+}
+
+#[test]
+fn writable_full_send_buffer() -> Result {
+    let mut sim = Builder::new().build();
+
+    sim.host("server", || async {
+        let listener = bind().await?;
+
+        loop {
+            let (mut s, _) = listener.accept().await?;
+
+            let mut buf = vec![];
+            s.read_to_end(&mut buf).await?;
+            assert_eq!(b"hello", &buf[..]);
+        }
+    });
+
+    sim.client("client", async {
+        let s = TcpStream::connect(("server", PORT)).await?;
+        s.set_send_buffer_size(4)?;
+
+        turmoil::hold("client", "server");
+        assert_eq!(4, s.try_write(b"hello")?);
+
+        // not writable until the buffered data is delivered
+        assert!(timeout(Duration::from_millis(500), s.writable())
+            .await
+            .is_err());
+        assert!(
+            timeout(Duration::from_millis(500), s.ready(Interest::WRITABLE))
+                .await
+                .is_err()
+        );
+
+        turmoil::release("client", "server");
+
+        assert!(s.ready(Interest::WRITABLE).await?.is_writable());
+        assert_eq!(1, s.try_write(b"o")?);
+
+        Ok(())
+    });
+
+    sim.run()
+}
+
+#[test]
+fn ready() -> Result {
+    let mut sim = Builder::new().build();
+
+    sim.client("server", async move {
+        let listener = bind().await?;
+        let (s, _) = listener.accept().await?;
+
+        let guard = s.ready(Interest::READABLE | Interest::WRITABLE).await?;
+        assert!(guard.is_writable());
+        assert!(!guard.is_readable());
+
+        let guard = s.ready(Interest::READABLE).await?;
+        assert!(guard.is_readable());
+
+        let mut buf = [0; 8];
+        let n = guard.try_read(&mut buf)?;
+        assert_eq!(b"hello", &buf[..n]);
+        assert_eq!(5, guard.try_write(b"world")?);
+
+        Ok(())
+    });
+
+    sim.client("client", async move {
+        let mut s = TcpStream::connect(("server", PORT)).await?;
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        s.write_all(b"hello").await?;
+
+        let mut buf = [0; 5];
+        s.read_exact(&mut buf).await?;
+        assert_eq!(b"world", &buf);
+
+        Ok(())
+    });
+
+    sim.run()
+}
+
+#[test]
+fn buf_read_lines() -> Result {
+    let mut sim = Builder::new().build();
+
+    sim.client("server", async move {
+        let listener = bind().await?;
+        let (s, _) = listener.accept().await?;
+
+        let mut lines = s.lines();
+        assert_eq!(
+            Some("HELO example.com".to_string()),
+            lines.next_line().await?
+        );
+        assert_eq!(Some("QUIT".to_string()), lines.next_line().await?);
+        assert_eq!(None, lines.next_line().await?);
+
+        Ok(())
+    });
+
+    sim.client("client", async move {
+        let mut s = TcpStream::connect(("server", PORT)).await?;
+
+        s.write_all(b"HELO exa").await?;
+        s.write_all(b"mple.com\r\nQUIT\n").await?;
+
+        Ok(())
+    });
+
+    sim.run()
+}
+
+#[test]
+fn poll_accept() -> Result {
+    let mut sim = Builder::new().build();
+
+    sim.client("server", async move {
+        let a = TcpListener::bind((IpAddr::from(Ipv4Addr::UNSPECIFIED), PORT)).await?;
+        let b = TcpListener::bind((IpAddr::from(Ipv4Addr::UNSPECIFIED), PORT + 1)).await?;
+
+        let mut ports = vec![];
+        for _ in 0..2 {
+            let port = tokio::select! {
+                res = future::poll_fn(|cx| a.poll_accept(cx)) => res?.0.local_addr()?.port(),
+                res = future::poll_fn(|cx| b.poll_accept(cx)) => res?.0.local_addr()?.port(),
+            };
+            ports.push(port);
+        }
+
+        ports.sort();
+        assert_eq!(vec![PORT, PORT + 1], ports);
+
+        Ok(())
+    });
+
+    sim.client("client", async move {
+        let _a = TcpStream::connect(("server", PORT + 1)).await?;
+        let _b = TcpStream::connect(("server", PORT)).await?;
+
+        Ok(())
+    });
+
+    sim.run()
+}
+
+#[test]
+fn connect_with_options() -> Result {
+    let mut sim = Builder::new().build();
+
+    sim.host("server", || async move {
+        let listener = bind().await?;
+
+        loop {
+            let (_s, origin) = listener.accept().await?;
+            assert_eq!(4321, origin.port());
+        }
+    });
+
+    sim.client("client", async move {
+        let opts = ConnectOptions {
+            bind_addr: Some((IpAddr::from(Ipv4Addr::UNSPECIFIED), 4321).into()),
+            ..Default::default()
+        };
+        let s = TcpStream::connect_with_options(("server", PORT), opts.clone()).await?;
+        assert_eq!(4321, s.local_addr()?.port());
+
+        assert_error_kind(
+            TcpStream::connect_with_options(("server", PORT), opts).await,
+            io::ErrorKind::AddrInUse,
+        );
+
+        turmoil::hold("client", "server");
+
+        let opts = ConnectOptions {
+            timeout: Some(Duration::from_secs(1)),
+            ..Default::default()
+        };
+        assert_error_kind(
+            TcpStream::connect_with_options(("server", PORT), opts).await,
+            io::ErrorKind::TimedOut,
+        );
+
+        Ok(())
+    });
+
+    sim.run()
+}
+
+#[test]
+fn read_exact_timeout() -> Result {
+    let mut sim = Builder::new().build();
+
+    sim.host("server", || async {
+        let listener = bind().await?;
+
+        loop {
+            let (mut s, _) = listener.accept().await?;
+
+            s.write_all(b"he").await?;
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            s.write_all(b"llo").await?;
+
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            s.write_all(b"!").await?;
+        }
+    });
+
+    sim.client("client", async {
+        let mut s = TcpStream::connect(("server", PORT)).await?;
+
+        let mut buf = [0; 5];
+        let n = s
+            .read_exact_timeout(&mut buf, Duration::from_millis(500))
+            .await?;
+        assert_eq!(5, n);
+        assert_eq!(b"hello", &buf);
+
+        assert_error_kind(
+            s.read_exact_timeout(&mut buf[..1], Duration::from_millis(500))
+                .await,
+            io::ErrorKind::TimedOut,
+        );
+
+        Ok(())
+    });
+
+    sim.run()
+}
+
+#[test]
+fn connection_limit() -> Result {
+    let mut sim = Builder::new().build();
+
+    sim.host("server", || async {
+        let listener = bind().await?;
+        let mut streams = vec![];
+
+        loop {
+            let (s, _) = listener.accept().await?;
+            streams.push(s);
+        }
+    });
+
+    sim.client("a", async {
+        let _s1 = TcpStream::connect(("server", PORT)).await?;
+        let _s2 = TcpStream::connect(("server", PORT)).await?;
+
+        assert_error_kind(
+            TcpStream::connect(("server", PORT)).await,
+            io::ErrorKind::ConnectionRefused,
+        );
+
+        Ok(())
+    });
+
+    sim.client("b", async {
+        assert_error_kind(
+            TcpStream::connect(("server", PORT)).await,
+            io::ErrorKind::ConnectionRefused,
+        );
+
+        Ok(())
+    });
+
+    sim.set_connection_limit("server", 2);
+    sim.set_connection_limit("b", 0);
+
+    sim.run()
+}
+
+#[test]
+fn write_vectored() -> Result {
+    let mut sim = Builder::new().build();
+
+    sim.client("server", async {
+        let listener = bind().await?;
+        let (mut s, _) = listener.accept().await?;
+
+        let mut buf = [0; 11];
+        s.read_exact(&mut buf).await?;
+        assert_eq!(b"hello world", &buf);
+
+        Ok(())
+    });
+
+    sim.client("client", async {
+        let mut s = TcpStream::connect(("server", PORT)).await?;
+        assert!(s.is_write_vectored());
+
+        let bufs = [io::IoSlice::new(b"hello"), io::IoSlice::new(b" world")];
+        let n = s.write_vectored(&bufs).await?;
+        assert_eq!(11, n);
+
+        let (_, w) = s.into_split();
+        assert!(w.is_write_vectored());
+
+        Ok(())
+    });
+
+    sim.run()
+}
+
+#[test]
+fn try_write_vectored() -> Result {
+    let mut sim = Builder::new()
+        .min_message_latency(Duration::from_millis(100))
+        .max_message_latency(Duration::from_millis(100))
+        .build();
+
+    sim.set_default_tcp_buffer_size(8);
+
+    sim.client("server", async {
+        let listener = bind().await?;
+        let (mut s, _) = listener.accept().await?;
+
+        let mut buf = vec![];
+        s.read_to_end(&mut buf).await?;
+        assert_eq!(b"hello world", &buf[..]);
+
+        Ok(())
+    });
+
+    sim.client("client", async {
+        let mut s = TcpStream::connect(("server", PORT)).await?;
+
+        let bufs = [io::IoSlice::new(b"hello"), io::IoSlice::new(b" world")];
+        assert_eq!(8, s.try_write_vectored(&bufs)?);
+        assert_error_kind(s.try_write_vectored(&bufs), io::ErrorKind::WouldBlock);
+        assert_eq!(0, s.try_write_vectored(&[])?);
+
+        s.writable().await?;
+        let bufs = [io::IoSlice::new(b"r"), io::IoSlice::new(b"ld")];
+        assert_eq!(3, s.try_write_vectored(&bufs)?);
+        s.shutdown().await?;
+
+        Ok(())
+    });
+
+    sim.run()
+}
+
+#[test]
+fn connect_lazy() -> Result {
+    let mut sim = Builder::new().build();
+
+    sim.client("server", async {
+        let listener = bind().await?;
+        let _ = listener.accept().await?;
+
+        Ok(())
+    });
+
+    sim.client("client", async {
+        let s = TcpStream::connect_lazy(async {
+            let ip = turmoil::resolve_async("server").await.unwrap();
+            (ip, PORT)
+        })
+        .await?;
+
+        assert_eq!(turmoil::lookup("server"), s.peer_addr()?.ip());
+
+        Ok(())
+    });
+
+    sim.run()
+}
+
+#[test]
+fn kill_connection_between() -> Result {
+    let addrs = Rc::new(Cell::new(None));
+    let connected = addrs.clone();
+
+    let mut sim = Builder::new().build();
+
+    sim.client("server", async {
+        let listener = bind().await?;
+        let (mut s, _) = listener.accept().await?;
+
+        let mut buf = [0; 8];
+        assert_error_kind(s.read(&mut buf).await, io::ErrorKind::ConnectionReset);
+
+        Ok(())
+    });
+
+    sim.client("client", async move {
+        let mut s = TcpStream::connect(("server", PORT)).await?;
+        connected.set(Some((s.local_addr()?, s.peer_addr()?)));
+
+        let mut buf = [0; 8];
+        assert_error_kind(s.read(&mut buf).await, io::ErrorKind::ConnectionReset);
+
+        Ok(())
+    });
+
+    let (local, peer) = loop {
+        sim.step()?;
+
+        if let Some(addrs) = addrs.get() {
+            break addrs;
+        }
+    };
+
+    assert!(sim.kill_connection_between(local, peer));
+    assert!(!sim.kill_connection_between(local, peer));
+
+    sim.run()
+}
+
+#[test]
+fn connect_cancellable() -> Result {
+    let mut sim = Builder::new().build();
+
+    sim.host("server", || async {
+        let listener = bind().await?;
+
+        loop {
+            let _ = listener.accept().await?;
+        }
+    });
+
+    sim.client("client", async {
+        let token = CancellationToken::new();
+        TcpStream::connect_cancellable(("server", PORT), token.clone()).await?;
+
+        turmoil::hold("client", "server");
+
+        let cancel = token.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            cancel.cancel();
+        });
+
+        assert_error_kind(
+            TcpStream::connect_cancellable(("server", PORT), token).await,
+            io::ErrorKind::Interrupted,
+        );
+
+        Ok(())
+    });
+
+    sim.run()
+}
+
+#[test]
+fn tls() -> Result {
+    use tokio_rustls::rustls::{
+        pki_types::{PrivateKeyDer, ServerName},
+        ClientConfig, RootCertStore, ServerConfig,
+    };
+    use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+    let cert = rcgen::generate_simple_self_signed(vec!["server".to_string()])?;
+    let key = PrivateKeyDer::Pkcs8(cert.key_pair.serialize_der().into());
+
+    let server_config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(vec![cert.cert.der().clone()], key)?;
+    let acceptor = TlsAcceptor::from(Arc::new(server_config));
+
+    let mut roots = RootCertStore::empty();
+    roots.add(cert.cert.der().clone())?;
+    let client_config = ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let connector = TlsConnector::from(Arc::new(client_config));
+
+    let mut sim = Builder::new().build();
+
+    sim.client("server", async move {
+        let listener = bind().await?;
+        let (s, _) = listener.accept().await?;
+        let mut s = acceptor.accept(s).await?;
+
+        let mut buf = [0; 5];
+        s.read_exact(&mut buf).await?;
+        s.write_all(&buf).await?;
+        s.shutdown().await?;
+
+        Ok(())
+    });
+
+    sim.client("client", async move {
+        let s = TcpStream::connect(("server", PORT)).await?;
+        let mut s = connector
+            .connect(ServerName::try_from("server")?, s)
+            .await?;
+
+        s.write_all(b"hello").await?;
+
+        let mut buf = vec![];
+        s.read_to_end(&mut buf).await?;
+        assert_eq!(b"hello", &buf[..]);
+
+        Ok(())
+    });
+
+    sim.run()
+}
+
+#[test]
+fn recv_timeout() -> Result {
+    let mut sim = Builder::new().build();
+
+    sim.host("server", || async {
+        let listener = bind().await?;
+
+        loop {
+            let (mut s, _) = listener.accept().await?;
+
+            tokio::time::sleep(Duration::from_millis(400)).await;
+            s.write_all(b"a").await?;
+            tokio::time::sleep(Duration::from_millis(400)).await;
+            s.write_all(b"b").await?;
+
+            future::pending::<()>().await;
+        }
+    });
+
+    sim.client("client", async {
+        let mut s = TcpStream::connect(("server", PORT)).await?;
+        assert_eq!(None, s.recv_timeout()?);
+
+        assert_error_kind(
+            s.set_recv_timeout(Some(Duration::ZERO)),
+            io::ErrorKind::InvalidInput,
+        );
+
+        s.set_recv_timeout(Some(Duration::from_millis(500)))?;
+        assert_eq!(Some(Duration::from_millis(500)), s.recv_timeout()?);
+
+        // each read restarts the timeout
+        let mut buf = [0; 1];
+        s.read_exact(&mut buf).await?;
+        s.read_exact(&mut buf).await?;
+        assert_eq!(b"b", &buf);
+
+        assert_error_kind(s.read(&mut buf).await, io::ErrorKind::TimedOut);
+
+        Ok(())
+    });
+
+    sim.run()
+}
+
+#[test]
+fn send_timeout() -> Result {
+    let mut sim = Builder::new().build();
+
+    sim.host("server", || async {
+        let listener = bind().await?;
+
+        let (mut s, _) = listener.accept().await?;
+
+        let mut buf = vec![];
+        s.read_to_end(&mut buf).await?;
+        assert_eq!(b"hellohello", &buf[..]);
+
+        Ok(())
+    });
+
+    sim.client("client", async {
+        let mut s = TcpStream::connect(("server", PORT)).await?;
+        assert_eq!(None, s.send_timeout()?);
+
+        assert_error_kind(
+            s.set_send_timeout(Some(Duration::ZERO)),
+            io::ErrorKind::InvalidInput,
+        );
+
+        s.set_send_timeout(Some(Duration::from_millis(500)))?;
+        assert_eq!(Some(Duration::from_millis(500)), s.send_timeout()?);
+
+        // without a buffer size writes never block, so they complete
+        // regardless of the timeout
+        s.write_all(b"hello").await?;
+
+        assert_eq!(None, s.send_buffer_size()?);
+        assert_error_kind(s.set_send_buffer_size(0), io::ErrorKind::InvalidInput);
+        s.set_send_buffer_size(4)?;
+        assert_eq!(Some(4), s.send_buffer_size()?);
+
+        turmoil::hold("client", "server");
+
+        assert_eq!(4, s.write(b"hello").await?);
+        assert_error_kind(s.try_write(b"o"), io::ErrorKind::WouldBlock);
+
+        let start = tokio::time::Instant::now();
+        assert_error_kind(s.write(b"o").await, io::ErrorKind::TimedOut);
+        assert_eq!(Duration::from_millis(500), start.elapsed());
+
+        turmoil::release("client", "server");
+
+        // the write completes once the buffered data is delivered
+        assert_eq!(1, s.write(b"o").await?);
+
+        Ok(())
+    });
+
+    sim.run()
+}
+
+#[test]
+fn send_timeout_after_cancelled_write() -> Result {
+    let mut sim = Builder::new().build();
+
+    sim.host("server", || async {
+        let listener = bind().await?;
+        let (mut s, _) = listener.accept().await?;
+
+        let mut buf = vec![];
+        s.read_to_end(&mut buf).await?;
+        assert_eq!(b"hello", &buf[..]);
+
+        Ok(())
+    });
+
+    sim.client("client", async {
+        let mut s = TcpStream::connect(("server", PORT)).await?;
+        s.set_send_buffer_size(4)?;
+        s.set_send_timeout(Some(Duration::from_millis(500)))?;
+
+        turmoil::hold("client", "server");
+        assert_eq!(4, s.write(b"hell").await?);
+
+        // cancel a blocked write before it times out
+        assert!(timeout(Duration::from_millis(400), s.write(b"o"))
+            .await
+            .is_err());
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+
+        // the next write that blocks gets the full timeout
+        let start = tokio::time::Instant::now();
+        assert_error_kind(s.write(b"o").await, io::ErrorKind::TimedOut);
+        assert_eq!(Duration::from_millis(500), start.elapsed());
+
+        turmoil::release("client", "server");
+        assert_eq!(1, s.write(b"o").await?);
+
+        Ok(())
+    });
+
+    sim.run()
+}
+
+#[test]
+fn default_tcp_buffer_size() -> Result {
+    let mut sim = Builder::new()
+        .min_message_latency(Duration::from_millis(100))
+        .max_message_latency(Duration::from_millis(100))
+        .build();
+
+    sim.set_default_tcp_buffer_size(4);
+
+    sim.host("server", || async {
+        let listener = bind().await?;
+
+        loop {
+            let (mut s, _) = listener.accept().await?;
+
+            let mut buf = vec![];
+            s.read_to_end(&mut buf).await?;
+            assert_eq!(b"0123456789", &buf[..]);
+        }
+    });
+
+    sim.client("client", async {
+        let mut s = TcpStream::connect(("server", PORT)).await?;
+        assert_eq!(Some(4), s.send_buffer_size()?);
+
+        assert_eq!(4, s.try_write(b"012345")?);
+        assert_eq!(4, s.bytes_pending_write());
+        assert_error_kind(s.try_write(b"45"), io::ErrorKind::WouldBlock);
+
+        s.set_send_timeout(Some(Duration::from_millis(10)))?;
+        assert_error_kind(s.write_all(b"45").await, io::ErrorKind::TimedOut);
+        s.set_send_timeout(None)?;
+
+        // waits for the first write to be delivered
+        s.writable().await?;
+        assert_eq!(0, s.bytes_pending_write());
+
+        s.write_all(b"456789").await?;
+        s.shutdown().await?;
+
+        Ok(())
+    });
+
+    sim.run()
+}
+
+#[test]
+fn bytes_pending_read() -> Result {
+    let mut sim = Builder::new().build();
+
+    sim.host("server", || async {
+        let listener = bind().await?;
+
+        loop {
+            let (mut s, _) = listener.accept().await?;
+
+            // let the client observe an empty buffer first
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            s.write_all(b"hello").await?;
+            s.write_all(b" world").await?;
+            s.shutdown().await?;
+        }
+    });
+
+    sim.client("client", async {
+        let mut s = TcpStream::connect(("server", PORT)).await?;
+        assert_eq!(0, s.bytes_pending_read());
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(11, s.bytes_pending_read());
+
+        let mut buf = [0; 3];
+        s.read_exact(&mut buf).await?;
+        assert_eq!(8, s.bytes_pending_read());
+
+        let mut rest = vec![];
+        s.read_to_end(&mut rest).await?;
+        assert_eq!(b"lo world", &rest[..]);
+        assert_eq!(0, s.bytes_pending_read());
+
+        Ok(())
+    });
+
+    sim.run()
+}
+
+#[test]
+fn bytes_pending_write() -> Result {
+    let mut sim = Builder::new()
+        .min_message_latency(Duration::from_millis(10))
+        .max_message_latency(Duration::from_millis(10))
+        .build();
+
+    sim.host("server", || async {
+        let listener = bind().await?;
+
+        loop {
+            let (mut s, _) = listener.accept().await?;
+            let mut buf = [0; 5];
+            s.read_exact(&mut buf).await?;
+        }
+    });
+
+    sim.client("client", async {
+        let mut s = TcpStream::connect(("server", PORT)).await?;
+        assert_eq!(0, s.bytes_pending_write());
+
+        s.write_all(b"hello").await?;
+        assert_eq!(5, s.bytes_pending_write());
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(0, s.bytes_pending_write());
+
+        Ok(())
+    });
+
+    sim.run()
+}
+
+#[test]
+fn connect_v6() -> Result {
+    let mut sim = Builder::new().build();
+
+    let server = IpAddr::from(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 1));
+    let client = IpAddr::from(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 2));
+
+    sim.host(server, || async {
+        let listener = TcpListener::bind((Ipv6Addr::UNSPECIFIED, PORT)).await?;
+
+        loop {
+            let (mut s, peer) = listener.accept().await?;
+            assert!(peer.is_ipv6());
+            s.write_u8(1).await?;
+        }
+    });
+
+    sim.client("client", async move {
+        let mut s =
+            TcpStream::connect_v6((Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 1), PORT)).await?;
+        assert_eq!(client, s.local_addr()?.ip());
+        assert_eq!(1, s.read_u8().await?);
+
+        Ok(())
+    });
+    sim.add_host_interface("client", client);
+
+    sim.client("v4-only", async {
+        let res = TcpStream::connect_v6((Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 1), PORT)).await;
+        assert_error_kind(res, io::ErrorKind::NetworkUnreachable);
+
+        Ok(())
+    });
+
+    sim.run()
+}
+
+#[test]
+fn bind_ipv6_unspecified() -> Result {
+    let mut sim = Builder::new().build();
+
+    let server_v6 = IpAddr::from(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 1));
+
+    sim.host("server", move || async move {
+        let listener = TcpListener::bind(format!(":::{PORT}")).await?;
+        assert_eq!(SocketAddr::from((server_v6, PORT)), listener.local_addr()?);
+
+        loop {
+            let (_, peer) = listener.accept().await?;
+            assert!(peer.is_ipv6());
+        }
+    });
+    sim.add_host_interface("server", server_v6);
+
+    sim.client("client", async move {
+        let s = TcpStream::connect_v6((Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 1), PORT)).await?;
+        assert_eq!(server_v6, s.peer_addr()?.ip());
+
+        // Only IPv6 connections are accepted
+        assert_error_kind(
+            TcpStream::connect((turmoil::lookup("server"), PORT)).await,
+            io::ErrorKind::ConnectionRefused,
+        );
+
+        Ok(())
+    });
+    sim.add_host_interface(
+        "client",
+        IpAddr::from(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 2)),
+    );
+
+    sim.run()
+}
+
+#[test]
+fn happy_eyeballs() -> Result {
+    let mut sim = Builder::new().build();
+
+    let server_v6 = IpAddr::from(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 1));
+    let client_v6 = IpAddr::from(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 2));
+
+    sim.host("server", || async {
+        let listener = bind().await?;
+
+        loop {
+            let _ = listener.accept().await?;
+        }
+    });
+    sim.add_host_interface("server", server_v6);
+
+    sim.client("client", async move {
+        let s = TcpStream::connect(("server", PORT)).await?;
+        assert_eq!(server_v6, s.peer_addr()?.ip());
+        assert_eq!(client_v6, s.local_addr()?.ip());
+
+        Ok(())
+    });
+    sim.add_host_interface("client", client_v6);
+
+    sim.hold("client", "server");
+
+    // IPv4 is tried once IPv6 has not connected within 250ms
+    while sim.elapsed() < Duration::from_millis(200) {
+        sim.step()?;
+    }
+    assert_eq!(1, sim.num_messages_in_flight());
+
+    while sim.elapsed() < Duration::from_millis(300) {
+        sim.step()?;
+    }
+    assert_eq!(2, sim.num_messages_in_flight());
+
+    sim.release("client", "server");
+    sim.run()
+}
+
+#[test]
+fn flush_waits_for_delivery() -> Result {
+    let mut sim = Builder::new()
+        .min_message_latency(Duration::from_millis(50))
+        .max_message_latency(Duration::from_millis(50))
+        .build();
+
+    sim.host("server", || async {
+        let listener = bind().await?;
+
+        loop {
+            let (mut s, _) = listener.accept().await?;
+            let mut buf = [0; 5];
+            s.read_exact(&mut buf).await?;
+        }
+    });
+
+    sim.client("client", async {
+        let mut s = TcpStream::connect(("server", PORT)).await?;
+
+        let start = tokio::time::Instant::now();
+        s.write_all(b"hello").await?;
+        assert_eq!(start, tokio::time::Instant::now());
+
+        s.flush().await?;
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert_eq!(0, s.bytes_pending_write());
+
+        // nothing left to deliver
+        let start = tokio::time::Instant::now();
+        s.flush().await?;
+        assert_eq!(start, tokio::time::Instant::now());
+
+        Ok(())
+    });
+
+    sim.run()
+}
@@ -1,6 +1,7 @@
+use bytes::BufMut;
 use std::{
-    matches,
-    net::{IpAddr, Ipv4Addr, SocketAddr},
+    io::{self, IoSlice},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
     rc::Rc,
     sync::{atomic::AtomicUsize, atomic::Ordering},
     time::Duration,
@@ -180,7 +181,7 @@ fn hold_and_release() -> Result {
         send_ping(&sock).await?;
 
         let res = timeout(Duration::from_secs(1), recv_pong(&sock)).await;
-        assert!(matches!(res, Err(_)));
+        assert!(res.is_err());
 
         // resume the network. note that the client ping does not have to be
         // resent.
@@ -323,3 +324,406 @@ fn bulk_transfer() -> Result {
 
     sim.run()
 }
+
+#[test]
+fn batch_send_recv() -> Result {
+    let latency = Duration::from_millis(1);
+
+    let mut sim = Builder::new()
+        .min_message_latency(latency)
+        .max_message_latency(latency)
+        .build();
+
+    sim.client("server", async move {
+        let sock = bind().await?;
+
+        // wait for the whole batch to arrive
+        tokio::time::sleep(latency * 2).await;
+
+        let mut a = [0; 4];
+        let mut b = [0; 4];
+        let mut c = [0; 4];
+        let mut bufs = [&mut a[..], &mut b[..], &mut c[..]];
+
+        let received = sock.recv_mmsg(&mut bufs).await?;
+        assert_eq!(2, received.len());
+        assert_eq!((4, lookup("client")), (received[0].0, received[0].1.ip()));
+        assert_eq!(b"ping", &a);
+        assert_eq!(b"pong", &b);
+
+        Ok(())
+    });
+
+    sim.client("client", async move {
+        let sock = bind().await?;
+
+        let server = (lookup("server"), PORT).into();
+        let sent = sock
+            .send_mmsg(&[(b"ping", server), (b"pong", server)])
+            .await?;
+        assert_eq!(2, sent);
+
+        Ok(())
+    });
+
+    sim.run()
+}
+
+#[test]
+fn recv_from_with_dst() -> Result {
+    let mut sim = Builder::new().build();
+
+    sim.client("server", async move {
+        let sock = bind().await?;
+
+        let (_, origin, dst) = sock.recv_from_with_dst(&mut [0; 4]).await?;
+        assert_eq!(lookup("client"), origin.ip());
+        assert_eq!(lookup("server"), dst);
+
+        Ok(())
+    });
+
+    sim.client("client", async move {
+        let sock = bind().await?;
+        send_ping(&sock).await
+    });
+
+    sim.run()
+}
+
+#[test]
+fn into_std_unsupported() -> Result {
+    let mut sim = Builder::new().build();
+
+    sim.client("client", async move {
+        let sock = bind().await?;
+
+        let err = sock.into_std().unwrap_err();
+        assert_eq!(std::io::ErrorKind::Unsupported, err.kind());
+
+        Ok(())
+    });
+
+    sim.run()
+}
+
+#[test]
+fn recv_buffer_drop_tail() -> Result {
+    let mut sim = Builder::new().build();
+
+    sim.client("server", async move {
+        let sock = bind().await?;
+        sock.set_recv_buffer_size(10)?;
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+
+        // only two 4 byte pings fit in the buffer, the rest are dropped
+        try_recv_ping(&sock)?;
+        try_recv_ping(&sock)?;
+        assert!(try_recv_ping(&sock).is_err());
+
+        // reading frees up space for new datagrams
+        recv_ping(&sock).await?;
+
+        Ok(())
+    });
+
+    sim.client("client", async move {
+        let sock = bind().await?;
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        for _ in 0..5 {
+            send_ping(&sock).await?;
+        }
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        send_ping(&sock).await?;
+
+        Ok(())
+    });
+
+    sim.run()
+}
+
+#[test]
+fn split() -> Result {
+    let mut sim = Builder::new().build();
+
+    sim.client("server", async move {
+        let (recv, send) = bind().await?.split();
+        assert_eq!(recv.local_addr()?, send.local_addr()?);
+
+        let mut buf = [0; 4];
+        let (_, origin) = recv.recv_from(&mut buf).await?;
+        assert_eq!(b"ping", &buf);
+
+        // the receive half keeps the socket bound
+        drop(recv);
+        send.send_to(b"pong", origin).await?;
+
+        Ok(())
+    });
+
+    sim.client("client", async move {
+        let sock = bind().await?;
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        send_ping(&sock).await?;
+
+        let mut buf = [0; 4];
+        sock.recv_from(&mut buf).await?;
+        assert_eq!(b"pong", &buf);
+
+        Ok(())
+    });
+
+    sim.run()
+}
+
+#[test]
+fn recv_buf() -> Result {
+    let mut sim = Builder::new().build();
+
+    sim.client("server", async move {
+        let sock = bind().await?;
+
+        let mut buf = bytes::BytesMut::with_capacity(16);
+        buf.extend_from_slice(b"> ");
+
+        let (n, _) = sock.recv_buf(&mut buf).await?;
+        assert_eq!(4, n);
+        assert_eq!(b"> ping", &buf[..]);
+
+        // excess bytes are discarded
+        let mut small = Vec::with_capacity(2).limit(2);
+        sock.recv_buf(&mut small).await?;
+        assert_eq!(b"pi", &small.into_inner()[..]);
+
+        Ok(())
+    });
+
+    sim.client("client", async move {
+        let sock = bind().await?;
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        send_ping(&sock).await?;
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        send_ping(&sock).await?;
+
+        Ok(())
+    });
+
+    sim.run()
+}
+
+#[test]
+fn send_to_vectored() -> Result {
+    let mut sim = Builder::new().build();
+
+    sim.client("server", async move {
+        let sock = bind().await?;
+
+        let mut buf = [0; 16];
+        let (n, _) = sock.recv_from(&mut buf).await?;
+        assert_eq!(b"ping", &buf[..n]);
+
+        Ok(())
+    });
+
+    sim.client("client", async move {
+        let sock = bind().await?;
+
+        let bufs = [IoSlice::new(b"pi"), IoSlice::new(b""), IoSlice::new(b"ng")];
+        let n = sock
+            .send_to_vectored(&bufs, (lookup("server"), PORT))
+            .await?;
+        assert_eq!(4, n);
+
+        Ok(())
+    });
+
+    sim.run()
+}
+
+#[test]
+fn multicast_loop_v4() -> Result {
+    let mut sim = Builder::new().build();
+
+    sim.client("client", async move {
+        let sock = bind().await?;
+        assert!(sock.multicast_loop_v4()?);
+
+        sock.set_multicast_loop_v4(false)?;
+        assert!(!sock.multicast_loop_v4()?);
+
+        Ok(())
+    });
+
+    sim.run()
+}
+
+#[test]
+fn multicast() -> Result {
+    let group = SocketAddr::from((Ipv4Addr::new(239, 0, 0, 1), PORT));
+    let mut sim = Builder::new().build();
+
+    sim.client("member", async move {
+        let sock = bind().await?;
+        sock.join_multicast_v4(Ipv4Addr::new(239, 0, 0, 1), Ipv4Addr::UNSPECIFIED)?;
+
+        for _ in 0..2 {
+            let origin = recv_ping(&sock).await?;
+            assert_eq!(lookup("sender"), origin.ip());
+        }
+
+        Ok(())
+    });
+
+    sim.client("other", async move {
+        let sock = bind().await?;
+
+        let mut buf = [0; 8];
+        assert!(timeout(Duration::from_secs(1), sock.recv_from(&mut buf))
+            .await
+            .is_err());
+
+        Ok(())
+    });
+
+    sim.client("sender", async move {
+        let sock = bind().await?;
+        sock.join_multicast_v4(Ipv4Addr::new(239, 0, 0, 1), Ipv4Addr::UNSPECIFIED)?;
+
+        // give the other hosts a chance to bind
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        sock.send_to(b"ping", group).await?;
+        let origin = recv_ping(&sock).await?;
+        assert_eq!(sock.local_addr()?, origin);
+
+        sock.set_multicast_loop_v4(false)?;
+        sock.send_to(b"ping", group).await?;
+        assert!(timeout(Duration::from_secs(1), recv_ping(&sock))
+            .await
+            .is_err());
+
+        Ok(())
+    });
+
+    sim.run()
+}
+
+#[test]
+fn multicast_membership() -> Result {
+    let mut sim = Builder::new().build();
+
+    sim.client("client", async move {
+        let sock = bind().await?;
+        let group = Ipv4Addr::new(239, 0, 0, 1);
+
+        let err = sock
+            .join_multicast_v4(Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::UNSPECIFIED)
+            .unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+
+        sock.join_multicast_v4(group, Ipv4Addr::UNSPECIFIED)?;
+        let err = sock
+            .join_multicast_v4(group, Ipv4Addr::UNSPECIFIED)
+            .unwrap_err();
+        assert_eq!(io::ErrorKind::AddrInUse, err.kind());
+
+        sock.leave_multicast_v4(group, Ipv4Addr::UNSPECIFIED)?;
+        let err = sock
+            .leave_multicast_v4(group, Ipv4Addr::UNSPECIFIED)
+            .unwrap_err();
+        assert_eq!(io::ErrorKind::AddrNotAvailable, err.kind());
+
+        // no longer a member, so the datagram is not looped back
+        sock.send_to(b"ping", (group, PORT)).await?;
+        assert!(timeout(Duration::from_secs(1), recv_ping(&sock))
+            .await
+            .is_err());
+
+        Ok(())
+    });
+
+    sim.run()
+}
+
+#[test]
+fn read_timeout() -> Result {
+    let mut sim = Builder::new().build();
+
+    sim.client("server", async move {
+        let sock = bind().await?;
+        assert_eq!(None, sock.read_timeout()?);
+
+        let err = sock.set_read_timeout(Some(Duration::ZERO)).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+
+        sock.set_read_timeout(Some(Duration::from_millis(500)))?;
+        assert_eq!(Some(Duration::from_millis(500)), sock.read_timeout()?);
+
+        let mut buf = [0; 8];
+        let err = sock.recv_from(&mut buf).await.unwrap_err();
+        assert_eq!(io::ErrorKind::TimedOut, err.kind());
+        assert_eq!(Duration::from_millis(500), turmoil::elapsed());
+
+        sock.set_read_timeout(None)?;
+        recv_ping(&sock).await?;
+
+        Ok(())
+    });
+
+    sim.client("client", async move {
+        let sock = bind().await?;
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        send_ping(&sock).await?;
+
+        Ok(())
+    });
+
+    sim.run()
+}
+
+#[test]
+fn bind_ipv6_unspecified() -> Result {
+    let mut sim = Builder::new().build();
+
+    let server_v6 = IpAddr::from(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 1));
+
+    sim.client("server", async move {
+        let v6 = net::UdpSocket::bind(format!(":::{PORT}")).await?;
+        assert_eq!(SocketAddr::from((server_v6, PORT)), v6.local_addr()?);
+
+        let v4 = bind().await?;
+
+        let mut buf = [0; 4];
+        let (_, origin) = v6.recv_from(&mut buf).await?;
+        assert!(origin.is_ipv6());
+
+        let (_, origin) = v4.recv_from(&mut buf).await?;
+        assert!(origin.is_ipv4());
+
+        Ok(())
+    });
+    sim.add_host_interface("server", server_v6);
+
+    sim.client("client", async move {
+        let v6 = net::UdpSocket::bind((Ipv6Addr::UNSPECIFIED, 0)).await?;
+        v6.send_to(b"ping", (server_v6, PORT)).await?;
+
+        let v4 = bind_to(0).await?;
+        send_ping(&v4).await?;
+
+        Ok(())
+    });
+    sim.add_host_interface(
+        "client",
+        IpAddr::from(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 2)),
+    );
+
+    sim.run()
+}